@@ -0,0 +1,29 @@
+//! 盤面の生成から手詰まりまでをネイティブ環境で繰り返し、処理時間を表示する。
+//!
+//! `cargo run --release --features sim --example sim [回数]`
+
+use std::num::NonZeroUsize;
+use std::time::Duration;
+
+use shisen::sim::{simulate, SimReport};
+
+fn main() {
+    let count: u64 = std::env::args()
+        .nth(1)
+        .map_or(100, |s| s.parse().expect("count should be an integer"));
+
+    let ncol = NonZeroUsize::new(6).unwrap();
+    let nrow = NonZeroUsize::new(9).unwrap();
+
+    let reports: Vec<SimReport> = (0..count).map(|seed| simulate(ncol, nrow, seed)).collect();
+
+    let total = |f: fn(&SimReport) -> Duration| reports.iter().map(f).sum::<Duration>();
+    let cleared = reports.iter().filter(|report| report.cleared).count();
+
+    println!("runs:        {count}");
+    println!("cleared:     {cleared}");
+    println!("generate:    {:?}", total(|report| report.generate));
+    println!("shuffle:     {:?}", total(|report| report.shuffle));
+    println!("find_move:   {:?}", total(|report| report.find_move));
+    println!("random_move: {:?}", total(|report| report.random_move));
+}
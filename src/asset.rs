@@ -3,38 +3,157 @@ use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{HtmlAudioElement, HtmlImageElement, ImageBitmap};
 
+use crate::i18n::Text;
+use crate::save::SoundSettings;
 use crate::shisen::TILE_KIND_COUNT;
 use crate::util;
 
+/// 牌画像のテーマの (名前, 表示名)。先頭がデフォルト。
+pub const THEMES: [(&str, Text); 2] = [
+    ("classic", Text::ThemeClassic),
+    ("simple", Text::ThemeSimple),
+];
+
+/// デフォルトの牌画像のテーマ。
+pub const DEFAULT_THEME: &str = THEMES[0].0;
+
+/// 背景をグラデーションで描画するテーマ。それ以外のテーマは単色で描画する。
+const GRADIENT_BACKGROUND_THEMES: [&str; 1] = ["classic"];
+
+/// テーマの背景をグラデーションで描画するかどうかを返す。
+pub fn has_gradient_background(theme: &str) -> bool {
+    GRADIENT_BACKGROUND_THEMES.contains(&theme)
+}
+
 #[derive(Debug)]
 pub struct Asset {
+    theme: String,
     imgs_tile: Vec<ImageBitmap>,
     sound_pick: HtmlAudioElement,
+    /// 牌を取ったときの効果音の変種。空なら `sound_pick` を使う。
+    sounds_pick_variant: Vec<HtmlAudioElement>,
+    /// 牌を選択したときの効果音。要素が存在しなければ None。
+    sound_select: Option<HtmlAudioElement>,
+    sound: SoundSettings,
 }
 
 impl Asset {
-    pub async fn load() -> anyhow::Result<Self> {
-        let mut imgs_tile = Vec::<ImageBitmap>::with_capacity(TILE_KIND_COUNT);
-        for i in 0..TILE_KIND_COUNT {
-            let id = format!("asset_image_tile_{i:02}");
+    /// 指定したテーマのアセットを読み込む。牌の画像は牌種 `0..tile_kinds` の分のみ読み込む。
+    ///
+    /// テーマの画像要素が存在しない場合、デフォルトテーマの画像を使う。
+    /// 牌の画像を 1 つ読み込むたびに `on_progress(読み込み済みの数, 全体の数)` を呼ぶ。
+    ///
+    /// `tile_kinds` が `TILE_KIND_COUNT` より大きい場合、panic する。
+    pub async fn load(
+        theme: &str,
+        tile_kinds: usize,
+        on_progress: impl Fn(usize, usize),
+    ) -> anyhow::Result<Self> {
+        assert!(tile_kinds <= TILE_KIND_COUNT);
+
+        let mut imgs_tile = Vec::<ImageBitmap>::with_capacity(tile_kinds);
+        for i in 0..tile_kinds {
+            let id = image_tile_id(theme, i);
+            let id = if util::try_get_element_by_id::<HtmlImageElement>(&id).is_some() {
+                id
+            } else {
+                image_tile_id(DEFAULT_THEME, i)
+            };
             let img = load_image_bitmap(&id).await?;
             imgs_tile.push(img);
+            on_progress(i + 1, tile_kinds);
         }
 
         let sound_pick = load_html_audio_element("asset_sound_pick");
+        let sounds_pick_variant = load_sound_pick_variants();
+        let sound_select = util::try_get_element_by_id::<HtmlAudioElement>("asset_sound_select");
+
+        let sound = SoundSettings::load();
+        sound_pick.set_volume(sound.volume);
+        for sound_pick_variant in &sounds_pick_variant {
+            sound_pick_variant.set_volume(sound.volume);
+        }
+        if let Some(sound_select) = &sound_select {
+            sound_select.set_volume(sound.volume);
+        }
 
         Ok(Self {
+            theme: theme.to_owned(),
             imgs_tile,
             sound_pick,
+            sounds_pick_variant,
+            sound_select,
+            sound,
         })
     }
 
+    pub fn theme(&self) -> &str {
+        &self.theme
+    }
+
     pub fn image_tile(&self, tile: usize) -> &ImageBitmap {
         &self.imgs_tile[tile]
     }
 
-    pub fn sound_pick(&self) -> &HtmlAudioElement {
-        &self.sound_pick
+    /// 牌を取ったときの効果音を鳴らす。ミュート中は何もしない。
+    ///
+    /// 効果音の変種がある場合、`variant` を変種の数で割った余りの番号の変種を鳴らす。
+    /// 変種がなければ `variant` に関わらず単一の効果音を鳴らす。
+    pub fn play_pick(&self, variant: usize) {
+        if self.sound.muted {
+            return;
+        }
+
+        let sound = if self.sounds_pick_variant.is_empty() {
+            &self.sound_pick
+        } else {
+            &self.sounds_pick_variant[variant % self.sounds_pick_variant.len()]
+        };
+        let _ = sound.play().unwrap();
+    }
+
+    /// 牌を選択したときの効果音を鳴らす。ミュート中や、効果音がない場合は何もしない。
+    pub fn play_select(&self) {
+        if let Some(sound_select) = self.sound_select.as_ref().filter(|_| !self.sound.muted) {
+            let _ = sound_select.play().unwrap();
+        }
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.sound.muted
+    }
+
+    /// ミュートを設定し、localStorage に保存する。
+    pub fn set_muted(&mut self, muted: bool) {
+        self.sound.muted = muted;
+        self.sound.store();
+    }
+
+    pub fn volume(&self) -> f64 {
+        self.sound.volume
+    }
+
+    /// 音量 (0.0..=1.0 に丸める) を設定し、localStorage に保存する。
+    pub fn set_volume(&mut self, volume: f64) {
+        let volume = volume.clamp(0.0, 1.0);
+        self.sound.volume = volume;
+        self.sound_pick.set_volume(volume);
+        for sound_pick_variant in &self.sounds_pick_variant {
+            sound_pick_variant.set_volume(volume);
+        }
+        if let Some(sound_select) = &self.sound_select {
+            sound_select.set_volume(volume);
+        }
+        self.sound.store();
+    }
+}
+
+/// 牌画像の要素 ID を返す。デフォルトテーマの ID にはテーマ名を含めない。
+fn image_tile_id(theme: &str, tile: usize) -> String {
+    if theme == DEFAULT_THEME {
+        format!("asset_image_tile_{tile:02}")
+    } else {
+        format!("asset_image_{theme}_tile_{tile:02}")
     }
 }
 
@@ -58,3 +177,11 @@ async fn load_image_bitmap(id: &str) -> anyhow::Result<ImageBitmap> {
 fn load_html_audio_element(id: &str) -> HtmlAudioElement {
     util::get_element_by_id(id)
 }
+
+/// 牌を取ったときの効果音の変種を、要素 ID `asset_sound_pick_00`, `asset_sound_pick_01`, ...
+/// から存在する限り読み込む。
+fn load_sound_pick_variants() -> Vec<HtmlAudioElement> {
+    (0..)
+        .map_while(|i| util::try_get_element_by_id(format!("asset_sound_pick_{i:02}")))
+        .collect()
+}
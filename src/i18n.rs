@@ -0,0 +1,229 @@
+//! UI の文字列の翻訳。
+
+/// UI の言語。
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Lang {
+    #[default]
+    En,
+    Ja,
+}
+
+impl Lang {
+    /// BCP 47 の言語タグ (`navigator.language` の値など) に対応する言語を返す。
+    /// 対応していない言語なら英語を返す。
+    pub fn from_tag(tag: &str) -> Self {
+        let primary = tag.split('-').next().unwrap_or_default();
+
+        if primary.eq_ignore_ascii_case("ja") {
+            Self::Ja
+        } else {
+            Self::En
+        }
+    }
+
+    /// 文字列 `text` をこの言語で返す。
+    pub fn tr(self, text: Text) -> &'static str {
+        let (en, ja) = text.strings();
+
+        match self {
+            Self::En => en,
+            Self::Ja => ja,
+        }
+    }
+}
+
+/// 翻訳対象の文字列。数値などを埋め込む文字列は、その前後の部分のみを持つ。
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Text {
+    Loading,
+    CannotLoadAsset,
+    Retry,
+    Restart,
+    ReplayThisBoard,
+    CopyReplay,
+    Clear,
+    Stuck,
+    TimeUp,
+    Score,
+    Hints,
+    TimeLeft,
+    Tiles,
+    Pairs,
+    Cleared,
+    Combo,
+    NoSolutionRemains,
+    Shuffle,
+    Pause,
+    Resume,
+    Hint,
+    SmartHint,
+    Undo,
+    CheckSolvable,
+    Solvable,
+    NotSolvable,
+    AutoSolve,
+    Settings,
+    ShowRemainingByKind,
+    ResumeSavedGame,
+    Shortcuts,
+    AutoSolving,
+    Replaying,
+    Endless,
+    RoundedTiles,
+    ShowOuterBorder,
+    ShowTileNumbers,
+    ShowGrid,
+    WarnDeadEnd,
+    ContinuousMatch,
+    ShowTargets,
+    Countdown,
+    Go,
+    ClockOnFirstMove,
+    Animations,
+    SystemDefault,
+    ReducedMotion,
+    FullMotion,
+    Language,
+    BrowserDefault,
+    Custom,
+    Mute,
+    PathDisplay,
+    Close,
+    Kind,
+    ScoreTime,
+    ScoreMoves,
+    ScoreShortestPaths,
+    ScoreCombos,
+    CannotPlayReplay,
+    IllegalMoveInReplay,
+    CannotResumeSavedGame,
+    Easy,
+    Normal,
+    Hard,
+    NoTimeLimit,
+    Rush1Min,
+    Rush3Min,
+    Rush5Min,
+    NoGravity,
+    GravityDown,
+    GravityUp,
+    GravityLeft,
+    GravityRight,
+    NoSymmetry,
+    SymmetryMirror,
+    SymmetryPoint,
+    SquareTiles,
+    TallTiles,
+    ThemeClassic,
+    ThemeSimple,
+}
+
+impl Text {
+    /// (英語, 日本語) の文字列を返す。
+    fn strings(self) -> (&'static str, &'static str) {
+        match self {
+            Self::Loading => ("loading", "読み込み中"),
+            Self::CannotLoadAsset => ("cannot load asset: ", "アセットを読み込めません: "),
+            Self::Retry => ("Retry", "もう一度"),
+            Self::Restart => ("Restart", "新しいゲーム"),
+            Self::ReplayThisBoard => ("Replay this board", "同じ盤面で遊ぶ"),
+            Self::CopyReplay => ("Copy replay", "リプレイをコピー"),
+            Self::Clear => ("CLEAR!", "クリア!"),
+            Self::Stuck => ("STUCK...", "手詰まり..."),
+            Self::TimeUp => ("TIME UP!", "時間切れ!"),
+            Self::Score => ("Score", "スコア"),
+            Self::Hints => ("hint(s)", "ヒント"),
+            Self::TimeLeft => ("Left", "残り"),
+            Self::Tiles => ("Tiles", "牌"),
+            Self::Pairs => ("pairs", "組"),
+            Self::Cleared => ("Cleared", "消した牌"),
+            Self::Combo => ("Combo", "コンボ"),
+            Self::NoSolutionRemains => (
+                "No solution remains — shuffle? ",
+                "解がありません。シャッフルしますか? ",
+            ),
+            Self::Shuffle => ("Shuffle", "シャッフル"),
+            Self::Pause => ("Pause", "一時停止"),
+            Self::Resume => ("Resume", "再開"),
+            Self::Hint => ("Hint", "ヒント"),
+            Self::SmartHint => ("Smart hint", "賢いヒント"),
+            Self::Undo => ("Undo", "一手戻す"),
+            Self::CheckSolvable => ("Check solvable", "解があるか調べる"),
+            Self::Solvable => ("solvable", "解あり"),
+            Self::NotSolvable => ("no longer solvable", "解なし"),
+            Self::AutoSolve => ("Auto-solve", "自動で解く"),
+            Self::Settings => ("Settings", "設定"),
+            Self::ShowRemainingByKind => ("Show remaining by kind", "種類ごとの残りを表示"),
+            Self::ResumeSavedGame => ("Resume saved game", "保存したゲームを再開"),
+            Self::Shortcuts => (
+                "Shortcuts: R = restart, H = hint, U = undo",
+                "ショートカット: R = 新しいゲーム, H = ヒント, U = 一手戻す",
+            ),
+            Self::AutoSolving => ("Auto-solving...", "自動で解いています..."),
+            Self::Replaying => ("Replaying...", "リプレイ中..."),
+            Self::Endless => ("Endless", "エンドレス"),
+            Self::RoundedTiles => ("Rounded tiles", "牌を角丸にする"),
+            Self::ShowOuterBorder => ("Show outer border", "外周を表示"),
+            Self::ShowTileNumbers => ("Show tile numbers", "牌の番号を表示"),
+            Self::ShowGrid => ("Show grid (debug)", "グリッドを表示 (デバッグ)"),
+            Self::WarnDeadEnd => ("Warn when no solution remains", "解がなくなったら警告"),
+            Self::ContinuousMatch => (
+                "Keep selecting the same kind after a match",
+                "取った後も同じ種類の牌を選択",
+            ),
+            Self::ShowTargets => (
+                "Highlight tiles the selection can take",
+                "選択中の牌で取れる牌を光らせる",
+            ),
+            Self::Countdown => ("Count down before starting", "開始前にカウントダウン"),
+            Self::Go => ("Go!", "スタート!"),
+            Self::ClockOnFirstMove => (
+                "Start the clock on the first move",
+                "最初の操作で計測を開始",
+            ),
+            Self::Animations => ("Animations", "アニメーション"),
+            Self::SystemDefault => ("System default", "システムの設定"),
+            Self::ReducedMotion => ("Reduced", "抑える"),
+            Self::FullMotion => ("Full", "すべて"),
+            Self::Language => ("Language", "言語"),
+            Self::BrowserDefault => ("Browser default", "ブラウザの設定"),
+            Self::Custom => ("Custom", "カスタム"),
+            Self::Mute => ("Mute", "ミュート"),
+            Self::PathDisplay => ("Path display", "経路の表示時間"),
+            Self::Close => ("Close", "閉じる"),
+            Self::Kind => ("kind", "種類"),
+            Self::ScoreTime => ("time", "時間"),
+            Self::ScoreMoves => ("moves", "手数"),
+            Self::ScoreShortestPaths => ("shortest paths", "最短経路"),
+            Self::ScoreCombos => ("combos", "コンボ"),
+            Self::CannotPlayReplay => ("cannot play replay: ", "リプレイを再生できません: "),
+            Self::IllegalMoveInReplay => (
+                "illegal move in replay: ",
+                "リプレイに不正な着手があります: ",
+            ),
+            Self::CannotResumeSavedGame => (
+                "cannot resume saved game: ",
+                "保存したゲームを再開できません: ",
+            ),
+            Self::Easy => ("Easy", "やさしい"),
+            Self::Normal => ("Normal", "ふつう"),
+            Self::Hard => ("Hard", "むずかしい"),
+            Self::NoTimeLimit => ("No time limit", "制限時間なし"),
+            Self::Rush1Min => ("Rush 1:00", "ラッシュ 1:00"),
+            Self::Rush3Min => ("Rush 3:00", "ラッシュ 3:00"),
+            Self::Rush5Min => ("Rush 5:00", "ラッシュ 5:00"),
+            Self::NoGravity => ("No gravity", "寄せない"),
+            Self::GravityDown => ("Down", "下に寄せる"),
+            Self::GravityUp => ("Up", "上に寄せる"),
+            Self::GravityLeft => ("Left", "左に寄せる"),
+            Self::GravityRight => ("Right", "右に寄せる"),
+            Self::NoSymmetry => ("No symmetry", "対称にしない"),
+            Self::SymmetryMirror => ("Mirror", "線対称"),
+            Self::SymmetryPoint => ("Point", "点対称"),
+            Self::SquareTiles => ("Square tiles", "正方形の牌"),
+            Self::TallTiles => ("Tall tiles (3:4)", "縦長の牌 (3:4)"),
+            Self::ThemeClassic => ("Classic", "クラシック"),
+            Self::ThemeSimple => ("Simple", "シンプル"),
+        }
+    }
+}
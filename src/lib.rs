@@ -1,5 +1,10 @@
 mod asset;
+mod i18n;
+mod save;
+mod score;
 pub mod shisen;
+#[cfg(feature = "sim")]
+pub mod sim;
 mod util;
 mod web;
 
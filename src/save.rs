@@ -0,0 +1,118 @@
+use seed::prelude::*;
+use serde::{Deserialize, Serialize};
+
+const STORAGE_KEY: &str = "shisen_saved_game";
+const STORAGE_KEY_SOUND: &str = "shisen_sound";
+const STORAGE_KEY_SETTINGS: &str = "shisen_settings";
+
+/// localStorage に保存する進行中のゲーム。
+///
+/// 現在の盤面は、開始時の盤面に着手を順に適用することで復元する。
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SavedGame {
+    /// 開始時の盤面の文字列表現。
+    pub board: String,
+    /// 牌を寄せる方向(UI 上の値)。
+    pub gravity: String,
+    /// 開始時からの着手(リプレイ形式)。着手数はこの着手の個数となる。
+    pub moves: String,
+    /// 経過時間(ミリ秒)。
+    pub elapsed_ms: u64,
+}
+
+impl SavedGame {
+    /// 保存されたゲームを読み込む。存在しないか壊れている場合は None を返す。
+    pub fn load() -> Option<Self> {
+        LocalStorage::get(STORAGE_KEY).ok()
+    }
+
+    /// ゲームを保存する。失敗しても無視する。
+    pub fn store(&self) {
+        let _ = LocalStorage::insert(STORAGE_KEY, self);
+    }
+
+    /// 保存されたゲームを削除する。
+    pub fn clear() {
+        let _ = LocalStorage::remove(STORAGE_KEY);
+    }
+}
+
+/// localStorage に保存する効果音の設定。
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct SoundSettings {
+    pub muted: bool,
+    /// 音量 (0.0..=1.0)。
+    pub volume: f64,
+}
+
+impl Default for SoundSettings {
+    fn default() -> Self {
+        Self {
+            muted: false,
+            volume: 1.0,
+        }
+    }
+}
+
+impl SoundSettings {
+    /// 保存された設定を読み込む。存在しないか壊れている場合はデフォルト値を返す。
+    pub fn load() -> Self {
+        LocalStorage::get(STORAGE_KEY_SOUND).unwrap_or_default()
+    }
+
+    /// 設定を保存する。失敗しても無視する。
+    pub fn store(&self) {
+        let _ = LocalStorage::insert(STORAGE_KEY_SOUND, self);
+    }
+}
+
+/// localStorage に保存するゲームの設定。効果音の設定は `SoundSettings` に別に保存する。
+///
+/// 値は UI 上の値で保存し、不正な値は読み込み側で無視する。
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StoredSettings {
+    /// 難易度。設定がどの難易度にも一致しない場合は None。
+    pub difficulty: Option<String>,
+    /// 牌を寄せる方向。
+    pub gravity: Option<String>,
+    /// 新しい盤面の対称性。
+    pub symmetry: Option<String>,
+    /// 牌画像のテーマ名。
+    pub theme: Option<String>,
+    /// 最終手の経路の表示時間(ミリ秒)。
+    pub path_duration_ms: Option<u32>,
+    pub rounded_tiles: Option<bool>,
+    /// アニメーションを抑制するかどうか。None なら OS の設定に従う。
+    pub reduced_motion: Option<bool>,
+    /// 牌の種類の番号を表示するかどうか。
+    pub show_kinds: Option<bool>,
+    /// 解がなくなったときに警告するかどうか。
+    pub warn_dead_end: Option<bool>,
+    /// 盤面の外周を描画するかどうか。
+    pub show_border: Option<bool>,
+    /// マスの縦横比。
+    pub tile_aspect: Option<String>,
+    /// 着手後に同じ種類の残りの牌を選択したままにするかどうか。
+    pub continuous_match: Option<bool>,
+    /// UI の言語。None ならブラウザの設定に従う。
+    pub lang: Option<String>,
+    /// 選択中の牌から取れる牌を光らせるかどうか。
+    pub show_targets: Option<bool>,
+    /// ゲーム開始前にカウントダウンするかどうか。
+    pub countdown: Option<bool>,
+    /// 最初の操作まで経過時間の計測を始めないかどうか。
+    pub clock_on_first_move: Option<bool>,
+}
+
+impl StoredSettings {
+    /// 保存された設定を読み込む。存在しないか壊れている場合はデフォルト値を返す。
+    pub fn load() -> Self {
+        LocalStorage::get(STORAGE_KEY_SETTINGS).unwrap_or_default()
+    }
+
+    /// 設定を保存する。失敗しても無視する。
+    pub fn store(&self) {
+        let _ = LocalStorage::insert(STORAGE_KEY_SETTINGS, self);
+    }
+}
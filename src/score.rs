@@ -0,0 +1,124 @@
+use std::time::Duration;
+
+/// スコアの基本点。スコアの計算式の調整はこのファイル内で行う。
+const BASE: u32 = 10000;
+
+/// 経過時間 1 秒あたりの減点。
+const PENALTY_PER_SEC: u32 = 10;
+
+/// 最小手数 (組の数) を超えた着手 1 手あたりの減点。
+const PENALTY_PER_EXCESS_MOVE: u32 = 100;
+
+/// 最短経路で取った着手 1 手あたりの加点。
+const BONUS_PER_SHORTEST: u32 = 20;
+
+/// コンボになった着手 1 手あたりの加点。
+const BONUS_PER_COMBO: u32 = 50;
+
+/// プレイ中に集計する、スコアの計算に使う値。
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PlayStats {
+    /// 最短経路だった着手の数。
+    pub shortest_used: usize,
+    /// コンボになった着手の数。
+    pub combos: usize,
+}
+
+/// スコアの内訳。
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Score {
+    pub base: u32,
+    /// 経過時間による減点。
+    pub time_penalty: u32,
+    /// 余分な着手による減点。
+    pub move_penalty: u32,
+    /// 最短経路による加点。
+    pub path_bonus: u32,
+    /// コンボによる加点。
+    pub combo_bonus: u32,
+}
+
+impl Score {
+    /// クリアまでの経過時間、着手数、盤面の組の数、プレイ中の集計値からスコアの内訳を計算する。
+    pub fn compute(elapsed: Duration, moves: usize, total_pairs: usize, stats: PlayStats) -> Self {
+        let to_u32 = |x: u64| u32::try_from(x).unwrap_or(u32::MAX);
+        let times = |n: usize, per: u32| to_u32((n as u64).saturating_mul(per.into()));
+
+        let time_penalty = to_u32(elapsed.as_secs().saturating_mul(PENALTY_PER_SEC.into()));
+        let move_penalty = times(moves.saturating_sub(total_pairs), PENALTY_PER_EXCESS_MOVE);
+        let path_bonus = times(stats.shortest_used, BONUS_PER_SHORTEST);
+        let combo_bonus = times(stats.combos, BONUS_PER_COMBO);
+
+        Self {
+            base: BASE,
+            time_penalty,
+            move_penalty,
+            path_bonus,
+            combo_bonus,
+        }
+    }
+
+    /// 合計点を返す。0 未満にはならない。
+    pub fn total(&self) -> u32 {
+        self.base
+            .saturating_add(self.path_bonus)
+            .saturating_add(self.combo_bonus)
+            .saturating_sub(self.time_penalty)
+            .saturating_sub(self.move_penalty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_elapsed() {
+        let score = Score::compute(Duration::ZERO, 27, 27, PlayStats::default());
+
+        assert_eq!(score.time_penalty, 0);
+        assert_eq!(score.move_penalty, 0);
+        assert_eq!(score.total(), BASE);
+    }
+
+    #[test]
+    fn penalties_saturate_at_zero() {
+        let score = Score::compute(Duration::from_secs(100_000), 100, 27, PlayStats::default());
+        assert_eq!(score.total(), 0);
+
+        let stats = PlayStats {
+            shortest_used: usize::MAX,
+            combos: usize::MAX,
+        };
+        let score = Score::compute(Duration::MAX, usize::MAX, 0, stats);
+        assert_eq!(score.total(), 0);
+    }
+
+    #[test]
+    fn excess_moves() {
+        let score = Score::compute(Duration::ZERO, 30, 27, PlayStats::default());
+        assert_eq!(score.move_penalty, 3 * PENALTY_PER_EXCESS_MOVE);
+        assert_eq!(score.total(), BASE - 3 * PENALTY_PER_EXCESS_MOVE);
+
+        // 組の数以下の着手数では減点しない。
+        let score = Score::compute(Duration::ZERO, 20, 27, PlayStats::default());
+        assert_eq!(score.move_penalty, 0);
+    }
+
+    #[test]
+    fn shortest_path_and_combo_bonuses() {
+        let stats = PlayStats {
+            shortest_used: 27,
+            combos: 2,
+        };
+        let score = Score::compute(Duration::from_secs(100), 27, 27, stats);
+
+        assert_eq!(score.time_penalty, 100 * PENALTY_PER_SEC);
+        assert_eq!(score.path_bonus, 27 * BONUS_PER_SHORTEST);
+        assert_eq!(score.combo_bonus, 2 * BONUS_PER_COMBO);
+        assert_eq!(
+            score.total(),
+            BASE + 27 * BONUS_PER_SHORTEST + 2 * BONUS_PER_COMBO - 100 * PENALTY_PER_SEC
+        );
+    }
+}
@@ -1,13 +1,66 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::num::NonZeroUsize;
 
+use anyhow::{anyhow, bail, ensure, Context as _};
 use itertools::{Either, Itertools as _};
 use rand::prelude::*;
+use rand::rngs::StdRng;
 
 use crate::util;
 
 /// 牌の種類数。
 pub const TILE_KIND_COUNT: usize = 34;
 
+/// `Board::random_with_opening()` で盤面を並べ直す最大回数。
+const OPENING_MAX_RETRY: usize = 100;
+
+/// `Board::random_symmetric()` で解を探索する局面数の上限。
+const SYMMETRIC_MAX_STATES: usize = 1000;
+
+/// `Board::random_symmetric()` で盤面を生成し直す最大回数。
+const SYMMETRIC_MAX_RETRY: usize = 100;
+
+/// `Board::shuffle_solvable()` で、1 手も着手できないシャッフルが続いた場合に諦める回数。
+const SHUFFLE_MAX_STALLS: usize = 1000;
+
+/// `Board::safe_move()` で着手後に先読みする手数。
+const SAFE_MOVE_LOOKAHEAD: usize = 2;
+
+/// 盤面の生成やシャッフルに使う乱数生成器。
+///
+/// 同じシードからは環境 (wasm を含む) によらず同じ乱数列が得られる。
+/// ただし `StdRng` のアルゴリズムは rand のバージョン間で変わり得るので、
+/// rand を更新するとシードに対応する盤面が変わることがある (テストで固定した盤面も更新する)。
+/// シードを指定しない場合はエントロピー源から初期化する。
+pub type BoardRng = StdRng;
+
+fn new_rng() -> BoardRng {
+    BoardRng::from_entropy()
+}
+
+/// 盤面の生成に使えるルールかどうかを確かめる。
+///
+/// `rule.tile_kinds` が範囲外の場合、または `rule.groups` 同士が牌種を共有するか
+/// 使わない牌種を含む場合、panic する。
+fn assert_valid_rule(rule: &Rule) {
+    assert!(
+        (1..=TILE_KIND_COUNT).contains(&rule.tile_kinds),
+        "tile_kinds should be in 1..={TILE_KIND_COUNT}"
+    );
+    assert!(
+        rule.groups.iter().flat_map(TileGroup::kinds).all_unique(),
+        "tile groups should be disjoint"
+    );
+    assert!(
+        rule.groups
+            .iter()
+            .flat_map(TileGroup::kinds)
+            .all(|&kind| kind < rule.tile_kinds),
+        "tile groups should contain only kinds < tile_kinds"
+    );
+}
+
 /// 盤面上のマス。
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct Square {
@@ -26,6 +79,8 @@ impl Square {
 pub enum BoardCell {
     Empty,
     Tile(usize),
+    /// 牌を置けないマス。経路上では牌と同様に障害物となる。
+    Blocked,
 }
 
 impl BoardCell {
@@ -37,10 +92,90 @@ impl BoardCell {
         matches!(self, Self::Tile(_))
     }
 
-    pub fn is_same_tile(self, other: BoardCell) -> bool {
-        match (self, other) {
-            (Self::Tile(kind1), Self::Tile(kind2)) => kind1 == kind2,
-            _ => false,
+    pub fn is_blocked(self) -> bool {
+        matches!(self, Self::Blocked)
+    }
+}
+
+/// 牌のグループ。
+///
+/// 同じグループに属する牌同士は、種類が異なっていても取ることができる(花牌、季節牌など)。
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct TileGroup {
+    kinds: Vec<usize>,
+}
+
+impl TileGroup {
+    /// 指定した牌種たちからなるグループを返す。
+    ///
+    /// `kinds` が空の場合、または `TILE_KIND_COUNT` 以上の牌種を含む場合、panic する。
+    pub fn new(kinds: impl IntoIterator<Item = usize>) -> Self {
+        let kinds: Vec<_> = kinds.into_iter().sorted().dedup().collect();
+
+        assert!(!kinds.is_empty(), "tile group should be nonempty");
+        assert!(
+            kinds.iter().all(|&kind| kind < TILE_KIND_COUNT),
+            "tile kind out of range"
+        );
+
+        Self { kinds }
+    }
+
+    /// グループに属する牌種を返す。
+    pub fn kinds(&self) -> &[usize] {
+        &self.kinds
+    }
+
+    /// 指定した牌種がグループに属するかどうかを返す。
+    pub fn contains(&self, kind: usize) -> bool {
+        self.kinds.binary_search(&kind).is_ok()
+    }
+}
+
+/// 牌を取った後、残りの牌を寄せる方向。
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum GravityMode {
+    /// 牌を寄せない。
+    #[default]
+    None,
+    Down,
+    Up,
+    Left,
+    Right,
+}
+
+/// 盤面の対称性。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Symmetry {
+    /// 左右対称。
+    Mirror,
+    /// 点対称。
+    Point,
+}
+
+/// 盤面のルール。
+#[derive(Clone, Debug)]
+pub struct Rule {
+    /// 牌のグループ。互いに牌種を共有してはならない。
+    pub groups: Vec<TileGroup>,
+
+    /// 牌を取った後、残りの牌を寄せる方向。
+    pub gravity: GravityMode,
+
+    /// 使う牌種の数。牌種 `0..tile_kinds` を使う。1 以上 `TILE_KIND_COUNT` 以下でなければならない。
+    pub tile_kinds: usize,
+
+    /// 外周のマスを経路に使えるかどうか。false なら経路は外周を除いた範囲に収まる必要がある。
+    pub allow_edge_routing: bool,
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Self {
+            groups: Vec::new(),
+            gravity: GravityMode::default(),
+            tile_kinds: TILE_KIND_COUNT,
+            allow_edge_routing: true,
         }
     }
 }
@@ -51,6 +186,12 @@ pub struct Board {
     ncol: NonZeroUsize,
     nrow: NonZeroUsize,
     cells: Vec<BoardCell>,
+    rule: Rule,
+    /// 合法手を持つ牌の組 (小さい方のマス, 大きい方のマス) のキャッシュ。None なら未計算。
+    ///
+    /// `count_moves()` で計算され、以降は `do_move()` で差分更新される。
+    /// マスを直接書き換えると破棄される。
+    move_pairs: RefCell<Option<HashSet<(Square, Square)>>>,
 }
 
 impl Board {
@@ -59,20 +200,45 @@ impl Board {
     /// `ncol_inner`, `nrow_inner` は外周を除くサイズ。
     /// 少なくとも一方は偶数でなければならない。
     ///
-    /// `ncol_inner * nrow_inner` がオーバーフローする場合、panic する。
+    /// 条件を満たさない場合やサイズがオーバーフローする場合、panic する。
+    /// ユーザー入力などのサイズには `try_empty()` を使う。
     pub fn empty(ncol_inner: NonZeroUsize, nrow_inner: NonZeroUsize) -> Self {
-        assert!(ncol_inner.get() % 2 == 0 || nrow_inner.get() % 2 == 0);
+        Self::try_empty(ncol_inner, nrow_inner).unwrap_or_else(|e| panic!("{e:#}"))
+    }
 
-        let ncol =
-            NonZeroUsize::new(ncol_inner.get().checked_add(2).expect("ncol overflow")).unwrap();
-        let nrow =
-            NonZeroUsize::new(nrow_inner.get().checked_add(2).expect("nrow overflow")).unwrap();
+    /// 空の盤面を返す。`empty()` の panic しない版。
+    ///
+    /// 両方の次元が奇数の場合や、外周を含むマス数がオーバーフローする場合はエラーを返す。
+    pub fn try_empty(ncol_inner: NonZeroUsize, nrow_inner: NonZeroUsize) -> anyhow::Result<Self> {
+        ensure!(
+            ncol_inner.get() % 2 == 0 || nrow_inner.get() % 2 == 0,
+            "board size {ncol_inner}x{nrow_inner}: both dimensions are odd"
+        );
+
+        Self::try_empty_any_parity(ncol_inner, nrow_inner)
+    }
 
-        let n = ncol.get().checked_mul(nrow.get()).expect("n overflow");
+    /// 空の盤面を返す。`try_empty()` と異なり、両方の次元が奇数でもよい。
+    ///
+    /// 牌の数の偶奇は呼び出し側で確かめる。
+    fn try_empty_any_parity(
+        ncol_inner: NonZeroUsize,
+        nrow_inner: NonZeroUsize,
+    ) -> anyhow::Result<Self> {
+        let overflow = || anyhow!("board size {ncol_inner}x{nrow_inner}: too large");
+        let ncol = ncol_inner.checked_add(2).ok_or_else(overflow)?;
+        let nrow = nrow_inner.checked_add(2).ok_or_else(overflow)?;
+        let n = ncol.get().checked_mul(nrow.get()).ok_or_else(overflow)?;
 
         let cells = vec![BoardCell::Empty; n];
 
-        Self { ncol, nrow, cells }
+        Ok(Self {
+            ncol,
+            nrow,
+            cells,
+            rule: Rule::default(),
+            move_pairs: RefCell::new(None),
+        })
     }
 
     /// ランダムな盤面を返す。解の存在が保証される。
@@ -80,34 +246,500 @@ impl Board {
     /// `ncol_inner`, `nrow_inner` は外周を除くサイズ。
     /// 少なくとも一方は偶数でなければならない。
     pub fn random(ncol_inner: NonZeroUsize, nrow_inner: NonZeroUsize) -> Self {
-        let mut this = Self::empty(ncol_inner, nrow_inner);
+        Self::random_with_rule(ncol_inner, nrow_inner, Rule::default())
+    }
 
-        // 全種類の牌をなるべく均等に出現させる。
-        // 端数の分はランダムに割り振る。
+    /// シードを指定してランダムな盤面を返す。解の存在が保証される。
+    ///
+    /// 同じシードからは環境によらず同じ盤面が得られる (rand のバージョンが同じ場合。`BoardRng` を参照)。
+    /// その他は `random()` と同じ。
+    pub fn random_seeded(ncol_inner: NonZeroUsize, nrow_inner: NonZeroUsize, seed: u64) -> Self {
+        let mut rng = BoardRng::seed_from_u64(seed);
 
-        let n_inner = ncol_inner.get() * nrow_inner.get();
-        let q = n_inner / (2 * TILE_KIND_COUNT);
-        let r = n_inner % (2 * TILE_KIND_COUNT);
+        Self::random_with_rng(ncol_inner, nrow_inner, &mut rng)
+    }
 
-        let mut tiles = Vec::<usize>::with_capacity(n_inner);
-        {
-            let mut xs: Vec<_> = (0..TILE_KIND_COUNT).collect();
-            for _ in 0..2 * q {
-                tiles.extend(xs.iter());
+    /// 乱数生成器を指定してランダムな盤面を返す。解の存在が保証される。
+    ///
+    /// 同じ状態の乱数生成器からは同じ盤面が得られるので、テストではシード付きの生成器を渡せばよい。
+    /// その他は `random()` と同じ。
+    pub fn random_with_rng(
+        ncol_inner: NonZeroUsize,
+        nrow_inner: NonZeroUsize,
+        rng: &mut impl Rng,
+    ) -> Self {
+        Self::generate(ncol_inner, nrow_inner, Rule::default(), |_| None, rng)
+            .expect("board without blocked squares should be generated")
+    }
+
+    /// 初期状態の合法手が `min_moves` 個以上あるランダムな盤面を返す。解の存在が保証される。
+    ///
+    /// `ncol_inner`, `nrow_inner` は外周を除くサイズ。
+    /// 少なくとも一方は偶数でなければならない。
+    ///
+    /// 条件を満たすまで牌を並べ直す。`OPENING_MAX_RETRY` 回並べ直しても満たさない場合、
+    /// それまでで最も合法手が多かった盤面を返す。
+    pub fn random_with_opening(
+        ncol_inner: NonZeroUsize,
+        nrow_inner: NonZeroUsize,
+        min_moves: usize,
+    ) -> Self {
+        Self::random_with_rule_and_opening(ncol_inner, nrow_inner, Rule::default(), min_moves)
+    }
+
+    /// ルールを指定し、初期状態の合法手が `min_moves` 個以上あるランダムな盤面を返す。
+    /// 解の存在はそのルールの下で保証される。
+    ///
+    /// 詳細は `random_with_opening()`, `random_with_rule()` を参照。
+    pub fn random_with_rule_and_opening(
+        ncol_inner: NonZeroUsize,
+        nrow_inner: NonZeroUsize,
+        rule: Rule,
+        min_moves: usize,
+    ) -> Self {
+        let rng = &mut new_rng();
+
+        let mut board = Self::generate(ncol_inner, nrow_inner, rule, |_| None, rng)
+            .expect("board without blocked squares should be generated");
+
+        let mut best_count = board.count_moves();
+        let mut best = board.clone();
+
+        for _ in 0..OPENING_MAX_RETRY {
+            if best_count >= min_moves {
+                break;
             }
-            xs.shuffle(&mut thread_rng());
-            for _ in 0..2 {
-                tiles.extend(xs[..r / 2].iter());
+
+            board
+                .shuffle_with_rng(rng)
+                .expect("board without blocked squares should be shuffled");
+            let count = board.count_moves();
+            if count > best_count {
+                best_count = count;
+                best = board.clone();
             }
         }
 
-        for (sq, tile) in itertools::zip_eq(this.squares_inner(), tiles) {
+        best
+    }
+
+    /// 牌のグループを指定してランダムな盤面を返す。解の存在が保証される。
+    ///
+    /// `ncol_inner`, `nrow_inner` は外周を除くサイズ。
+    /// 少なくとも一方は偶数でなければならない。
+    ///
+    /// `groups` 同士が牌種を共有する場合、panic する。
+    pub fn random_with_groups(
+        ncol_inner: NonZeroUsize,
+        nrow_inner: NonZeroUsize,
+        groups: Vec<TileGroup>,
+    ) -> Self {
+        let rule = Rule {
+            groups,
+            ..Default::default()
+        };
+
+        Self::random_with_rule(ncol_inner, nrow_inner, rule)
+    }
+
+    /// ルールを指定してランダムな盤面を返す。解の存在はそのルールの下で保証される。
+    ///
+    /// `ncol_inner`, `nrow_inner` は外周を除くサイズ。
+    /// 少なくとも一方は偶数でなければならない。
+    ///
+    /// `rule.groups` 同士が牌種を共有する場合、panic する。
+    pub fn random_with_rule(
+        ncol_inner: NonZeroUsize,
+        nrow_inner: NonZeroUsize,
+        rule: Rule,
+    ) -> Self {
+        Self::generate(ncol_inner, nrow_inner, rule, |_| None, &mut new_rng())
+            .expect("board without blocked squares should be generated")
+    }
+
+    /// 牌を置けないマスを指定してランダムな盤面を返す。解の存在はルールの下で保証される。
+    ///
+    /// `ncol_inner`, `nrow_inner` は外周を除くサイズ。
+    /// 外周を除くマスのうち `is_blocked` が true を返すものは `BoardCell::Blocked` になり、
+    /// 残りのマスに牌を置く。牌を置くマスの数が偶数なら、両方の次元が奇数でもよい。
+    ///
+    /// 牌を置けないマスに囲まれて他の牌と経路で結べないマスがあるなど、解のある配置が
+    /// 見つからない場合はエラーを返す (`shuffle_solvable()` を参照)。
+    ///
+    /// 牌を置くマスの数が奇数の場合、`rule.tile_kinds` が範囲外の場合、
+    /// または `rule.groups` 同士が牌種を共有するか使わない牌種を含む場合、panic する。
+    pub fn random_with_layout(
+        ncol_inner: NonZeroUsize,
+        nrow_inner: NonZeroUsize,
+        rule: Rule,
+        is_blocked: impl Fn(Square) -> bool,
+    ) -> anyhow::Result<Self> {
+        Self::generate(
+            ncol_inner,
+            nrow_inner,
+            rule,
+            |sq| is_blocked(sq).then_some(BoardCell::Blocked),
+            &mut new_rng(),
+        )
+    }
+
+    /// 両方の次元が奇数の盤面を、マス `hole` に牌を置かずにランダムに返す。
+    /// 解の存在はルールの下で保証される。
+    ///
+    /// 外周を除くマスの数が奇数なので、1 マスを牌を置かない空きマス (穴) にして牌の数を偶数にする。
+    /// 穴は他の空きマスと同様に経路が通れる。牌を寄せるルールでは、穴にも牌が寄る。
+    /// `hole` は外周を含む座標で指定する。
+    ///
+    /// 少なくとも一方の次元が偶数の場合や、`hole` が外周を除く範囲にない場合、panic する。
+    /// その他は `random_with_layout()` と同じ。
+    pub fn random_with_hole(
+        ncol_inner: NonZeroUsize,
+        nrow_inner: NonZeroUsize,
+        rule: Rule,
+        hole: Square,
+    ) -> Self {
+        assert!(
+            ncol_inner.get() % 2 == 1 && nrow_inner.get() % 2 == 1,
+            "board with a hole should have odd dimensions"
+        );
+        assert!(
+            (1..=ncol_inner.get()).contains(&hole.c) && (1..=nrow_inner.get()).contains(&hole.r),
+            "hole should be an inner square"
+        );
+
+        Self::generate(
+            ncol_inner,
+            nrow_inner,
+            rule,
+            |sq| (sq == hole).then_some(BoardCell::Empty),
+            &mut new_rng(),
+        )
+        .expect("board without blocked squares should be generated")
+    }
+
+    /// ランダムな盤面を生成する。解の存在はルールの下で保証される。
+    ///
+    /// 外周を除くマスのうち `fixed_cell` が Some を返すものはその中身になり、牌を置かない。
+    /// 残りのマスに牌を置く。解のある配置が見つからない場合はエラーを返す。
+    ///
+    /// `BoardCell::Blocked` のマスがなければ事実上エラーにならない (`shuffle_solvable()` を参照)。
+    fn generate(
+        ncol_inner: NonZeroUsize,
+        nrow_inner: NonZeroUsize,
+        rule: Rule,
+        fixed_cell: impl Fn(Square) -> Option<BoardCell>,
+        rng: &mut impl Rng,
+    ) -> anyhow::Result<Self> {
+        assert_valid_rule(&rule);
+
+        let mut this =
+            Self::try_empty_any_parity(ncol_inner, nrow_inner).unwrap_or_else(|e| panic!("{e:#}"));
+        this.rule = rule;
+
+        let mut sqs_tile = Vec::<Square>::new();
+        for sq in this.squares_inner() {
+            match fixed_cell(sq) {
+                Some(cell) => this[sq] = cell,
+                None => sqs_tile.push(sq),
+            }
+        }
+        assert_eq!(
+            sqs_tile.len() % 2,
+            0,
+            "number of squares for tiles should be even"
+        );
+
+        let tiles = this.random_tiles(sqs_tile.len() / 2, rng);
+
+        for (sq, tile) in itertools::zip_eq(sqs_tile, tiles) {
             this[sq] = BoardCell::Tile(tile);
         }
 
-        this.shuffle_solvable();
+        this.shuffle_with_rng(rng)?;
+
+        Ok(this)
+    }
+
+    /// 指定した対称性を持つランダムな盤面を返す。解の存在が保証される。
+    ///
+    /// `ncol_inner`, `nrow_inner` は外周を除くサイズ。
+    /// 少なくとも一方は偶数でなければならない。
+    ///
+    /// 対称な位置にあるマス同士に同じ種類の牌を置く。
+    /// 牌を並べ直すと対称性が崩れるので、解が見つかる配置が得られるまで生成し直す。
+    /// 解の探索は `SYMMETRIC_MAX_STATES` 局面で打ち切り、見つからなければ生成し直す。
+    /// `SYMMETRIC_MAX_RETRY` 回生成し直しても見つからない場合、対称でない盤面を返す。
+    pub fn random_symmetric(
+        ncol_inner: NonZeroUsize,
+        nrow_inner: NonZeroUsize,
+        symmetry: Symmetry,
+    ) -> Self {
+        Self::random_symmetric_with_rule(ncol_inner, nrow_inner, Rule::default(), symmetry)
+    }
+
+    /// ルールを指定して `random_symmetric()` と同様の盤面を返す。解の存在はルールの下で保証される。
+    ///
+    /// `rule.tile_kinds` が範囲外の場合、または `rule.groups` 同士が牌種を共有するか
+    /// 使わない牌種を含む場合、panic する。
+    pub fn random_symmetric_with_rule(
+        ncol_inner: NonZeroUsize,
+        nrow_inner: NonZeroUsize,
+        rule: Rule,
+        symmetry: Symmetry,
+    ) -> Self {
+        assert_valid_rule(&rule);
+        let mut rng = new_rng();
+
+        for _ in 0..SYMMETRIC_MAX_RETRY {
+            let mut this = Self::empty(ncol_inner, nrow_inner);
+            this.rule = rule.clone();
+
+            // 対称な位置にあるマスの組。
+            // 自身と対称なマスは、そのようなマス同士で組にする(各マスが対称なので対称性は崩れない)。
+            let mut sq_pairs = Vec::<(Square, Square)>::new();
+            let mut sqs_fixed = Vec::<Square>::new();
+            for sq in this.squares_inner() {
+                let image = this.symmetric_square(sq, symmetry);
+                match sq.cmp(&image) {
+                    std::cmp::Ordering::Less => sq_pairs.push((sq, image)),
+                    std::cmp::Ordering::Equal => sqs_fixed.push(sq),
+                    std::cmp::Ordering::Greater => {}
+                }
+            }
+            // 少なくとも一方の辺が偶数なので、自身と対称なマスは偶数個。
+            sq_pairs.extend(sqs_fixed.into_iter().tuples::<(Square, Square)>());
+            sq_pairs.shuffle(&mut rng);
+
+            let tiles = this.random_tiles(sq_pairs.len(), &mut rng);
+            for ((sq1, sq2), (tile1, tile2)) in
+                itertools::zip_eq(sq_pairs, tiles.into_iter().tuples())
+            {
+                this[sq1] = BoardCell::Tile(tile1);
+                this[sq2] = BoardCell::Tile(tile2);
+            }
+
+            // 縦横とも偶数の点対称では、中央の 2x2 マスで対角同士が同じ牌になり、互いに塞ぎ合って取れない。
+            // これを避けるため、中央の 4 マスを全て同じ牌にする。
+            if symmetry == Symmetry::Point && ncol_inner.get() % 2 == 0 && nrow_inner.get() % 2 == 0
+            {
+                let c = this.ncol.get() / 2;
+                let r = this.nrow.get() / 2;
+                let tile = this[Square::new(c - 1, r - 1)];
+                for sq in [
+                    Square::new(c, r - 1),
+                    Square::new(c - 1, r),
+                    Square::new(c, r),
+                ] {
+                    this[sq] = tile;
+                }
+            }
+
+            if this.solve_bounded(SYMMETRIC_MAX_STATES).is_some() {
+                return this;
+            }
+        }
+
+        // 解のある対称な配置が見つからなかったので、対称性を諦める。
+        Self::generate(ncol_inner, nrow_inner, rule, |_| None, &mut rng)
+            .expect("board without blocked squares should be generated")
+    }
+
+    /// `sq` と指定した対称性で対応するマスを返す。
+    fn symmetric_square(&self, sq: Square, symmetry: Symmetry) -> Square {
+        let c = self.ncol.get() - 1 - sq.c;
+        match symmetry {
+            Symmetry::Mirror => Square::new(c, sq.r),
+            Symmetry::Point => Square::new(c, self.nrow.get() - 1 - sq.r),
+        }
+    }
+
+    /// `n_pair` 組分の牌種をランダムに返す。連続する 2 枚ずつが互いに取れる牌の組になる。
+    ///
+    /// 全種類の牌を組単位でなるべく均等に出現させる。
+    /// 各牌種の組数の差は高々 1 で、端数の組はランダムな牌種に割り振る。
+    ///
+    /// グループに属する牌種はグループ全体で 1 種類とみなし、
+    /// 牌を置く際にグループ内の牌種を順に使う。
+    fn random_tiles(&self, n_pair: usize, rng: &mut impl Rng) -> Vec<usize> {
+        let mut units: Vec<Vec<usize>> = (0..self.rule.tile_kinds)
+            .filter(|&kind| self.group_of(kind).is_none())
+            .map(|kind| vec![kind])
+            .collect();
+        units.extend(self.rule.groups.iter().map(|group| group.kinds().to_vec()));
+
+        let q = n_pair / units.len();
+        let r = n_pair % units.len();
+
+        let mut tiles = Vec::<usize>::with_capacity(2 * n_pair);
+        {
+            let mut xs: Vec<_> = (0..units.len()).collect();
+            xs.shuffle(rng);
+
+            for (j, &i) in xs.iter().enumerate() {
+                let unit = &units[i];
+                let pair_count = q + usize::from(j < r);
+                tiles.extend(unit.iter().copied().cycle().take(2 * pair_count));
+            }
+        }
+        debug_assert_eq!(tiles.len(), 2 * n_pair);
+
+        tiles
+    }
+
+    /// 外周を除くマスの内容を行優先で並べたスライスから盤面を作る。None は空きマス。
+    ///
+    /// テストなどで盤面を手で組み立てるためのもの。ルールはデフォルト。
+    /// `kinds` の長さが `ncol_inner * nrow_inner` と異なる場合や、牌種が範囲外の場合、
+    /// 枚数が奇数の牌種がある場合はエラーを返す (`from_string_repr()` と同じ)。
+    pub fn from_kinds(
+        ncol_inner: NonZeroUsize,
+        nrow_inner: NonZeroUsize,
+        kinds: &[Option<usize>],
+    ) -> anyhow::Result<Self> {
+        let mut this = Self::try_empty(ncol_inner, nrow_inner)?;
+
+        let n_inner = ncol_inner.get() * nrow_inner.get();
+        ensure!(
+            kinds.len() == n_inner,
+            "board kinds: expected {n_inner} cells, got {}",
+            kinds.len()
+        );
+
+        for (sq, &kind) in itertools::zip_eq(this.squares_inner(), kinds) {
+            if let Some(kind) = kind {
+                ensure!(
+                    kind < this.rule.tile_kinds,
+                    "board kinds: invalid tile kind {kind}"
+                );
+                this[sq] = BoardCell::Tile(kind);
+            }
+        }
+
+        ensure!(
+            this.tiles_by_match_key()
+                .values()
+                .all(|sqs| sqs.len() % 2 == 0),
+            "board kinds: some tile kind has an odd count"
+        );
+
+        Ok(this)
+    }
+
+    /// 同じ大きさ、同じルールで、全てのマスが空の盤面を返す。
+    ///
+    /// 牌を置けないマスも空きマスになる。
+    pub fn clone_empty_like(&self) -> Self {
+        Self {
+            ncol: self.ncol,
+            nrow: self.nrow,
+            cells: vec![BoardCell::Empty; self.cells.len()],
+            rule: self.rule.clone(),
+            move_pairs: RefCell::new(None),
+        }
+    }
+
+    /// 文字列表現とルールから盤面を復元する。
+    ///
+    /// 形式は `to_string_repr()` を参照。両方の次元が奇数の盤面 (穴のある盤面) も復元できる。
+    /// サイズが不正な場合や、互いに取れる牌同士の枚数が偶数でない場合はエラーを返す。
+    pub fn from_string_repr(s: &str, rule: Rule) -> anyhow::Result<Self> {
+        let (dims, cells) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("board repr: missing ':'"))?;
+        let (ncol_inner, nrow_inner) = dims
+            .split_once('x')
+            .ok_or_else(|| anyhow!("board repr: missing 'x' in size"))?;
+
+        let ncol_inner: NonZeroUsize = ncol_inner
+            .parse()
+            .with_context(|| format!("board repr: invalid column count '{ncol_inner}'"))?;
+        let nrow_inner: NonZeroUsize = nrow_inner
+            .parse()
+            .with_context(|| format!("board repr: invalid row count '{nrow_inner}'"))?;
+        let n_inner = ncol_inner.get().checked_mul(nrow_inner.get());
+        ensure!(
+            n_inner == Some(cells.chars().count()),
+            "board repr: cell count mismatch"
+        );
+
+        let mut this = Self::try_empty_any_parity(ncol_inner, nrow_inner)?;
+        this.rule = rule;
+
+        for (sq, ch) in itertools::zip_eq(this.squares_inner(), cells.chars()) {
+            match ch {
+                '.' => continue,
+                '#' => {
+                    this[sq] = BoardCell::Blocked;
+                    continue;
+                }
+                _ => {}
+            }
+            let kind = ch
+                .to_digit(36)
+                .map(|kind| kind as usize)
+                .filter(|&kind| kind < this.rule.tile_kinds)
+                .ok_or_else(|| anyhow!("board repr: invalid cell '{ch}'"))?;
+            this[sq] = BoardCell::Tile(kind);
+        }
+
+        ensure!(
+            this.tiles_by_match_key()
+                .values()
+                .all(|sqs| sqs.len() % 2 == 0),
+            "board repr: some tile kind has an odd count"
+        );
+
+        Ok(this)
+    }
+
+    /// 盤面を文字列表現に変換する。ルールは含まない。
+    ///
+    /// 形式は `{ncol_inner}x{nrow_inner}:{cells}` で、`cells` は外周を除く全マスを行優先で並べたもの。
+    /// 牌は牌種を 36 進数 1 文字で、空きマスは `.` で、牌を置けないマスは `#` で表す。
+    pub fn to_string_repr(&self) -> String {
+        let ncol_inner = self.ncol.get() - 2;
+        let nrow_inner = self.nrow.get() - 2;
+
+        let cells: String = self
+            .squares_inner()
+            .map(|sq| match self[sq] {
+                BoardCell::Empty => '.',
+                BoardCell::Blocked => '#',
+                BoardCell::Tile(kind) => {
+                    char::from_digit(kind as u32, 36).expect("tile kind should be < 36")
+                }
+            })
+            .collect();
+
+        format!("{ncol_inner}x{nrow_inner}:{cells}")
+    }
+
+    /// ルールを返す。
+    pub fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    /// 牌のグループを返す。
+    pub fn groups(&self) -> &[TileGroup] {
+        &self.rule.groups
+    }
 
-        this
+    /// 指定した牌種が属するグループを返す。
+    pub fn group_of(&self, kind: usize) -> Option<&TileGroup> {
+        self.rule.groups.iter().find(|group| group.contains(kind))
+    }
+
+    /// 2 つのマスの中身が互いに取れる牌同士かどうかを返す。
+    ///
+    /// 同じ種類の牌同士、または同じグループに属する牌同士なら true を返す。
+    pub fn is_matching(&self, cell1: BoardCell, cell2: BoardCell) -> bool {
+        match (cell1, cell2) {
+            (BoardCell::Tile(kind1), BoardCell::Tile(kind2)) => {
+                kind1 == kind2
+                    || matches!(self.group_of(kind1), Some(group) if group.contains(kind2))
+            }
+            _ => false,
+        }
     }
 
     /// 列数を返す。
@@ -120,14 +752,88 @@ impl Board {
         self.nrow
     }
 
-    /// 盤面が空かどうかを返す。
+    /// 列 `c`, 行 `r` (外周を含む座標) のマスの状態を返す。盤面外なら None を返す。
+    ///
+    /// `Index<Square>` と異なり、範囲外の座標でも panic しない。
+    pub fn get(&self, c: usize, r: usize) -> Option<BoardCell> {
+        (c < self.ncol.get() && r < self.nrow.get()).then(|| self[Square::new(c, r)])
+    }
+
+    /// 盤面上に牌が残っていないかどうかを返す。牌を置けないマスは無視する。
     pub fn is_empty(&self) -> bool {
-        self.squares_inner().all(|sq| self[sq].is_empty())
+        self.squares_inner().all(|sq| !self[sq].is_tile())
+    }
+
+    /// 盤面上に残っている牌の数を返す。
+    pub fn tile_count(&self) -> usize {
+        self.iter_tiles().count()
     }
 
     /// 盤面が空でなく、かつ手詰まり状態かどうかを返す。
     pub fn is_stuck(&self) -> bool {
-        !self.is_empty() && self.find_move().is_none()
+        !self.is_empty() && self.count_moves() == 0
+    }
+
+    /// 盤面を空にできるかどうかを返す。
+    ///
+    /// `solve()` で全探索するので、局面によっては非常に時間がかかる。
+    pub fn is_solvable(&self) -> bool {
+        self.solve().is_some()
+    }
+
+    /// 盤面を空にするのに必要な最小の手数を返す。空にできない場合は None を返す。
+    ///
+    /// 1 手でちょうど 1 組取るので、空にできる盤面では牌の数の半分となる。
+    /// 実際に `solve()` で解を求めて確かめるので、時間がかかることがある。
+    pub fn min_moves_to_clear(&self) -> Option<usize> {
+        let moves = self.solve()?;
+        debug_assert_eq!(moves.len(), self.tile_count() / 2);
+
+        Some(moves.len())
+    }
+
+    /// 盤面を空にする着手の列を 0 または 1 個返す。
+    ///
+    /// 深さ優先で全探索する。同じ局面は 2 度調べない。
+    pub fn solve(&self) -> Option<Vec<Move>> {
+        self.solve_bounded(usize::MAX)
+    }
+
+    /// 調べる局面の数を `max_states` 以下に制限して `solve()` を行う。
+    ///
+    /// 制限に達した場合も None を返すので、None は解がないことを意味しない。
+    fn solve_bounded(&self, max_states: usize) -> Option<Vec<Move>> {
+        let mut visited = HashSet::<Vec<BoardCell>>::new();
+        let mut moves = Vec::<Move>::new();
+
+        self.solve_dfs(max_states, &mut visited, &mut moves)
+            .then_some(moves)
+    }
+
+    fn solve_dfs(
+        &self,
+        max_states: usize,
+        visited: &mut HashSet<Vec<BoardCell>>,
+        moves: &mut Vec<Move>,
+    ) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        if visited.len() >= max_states || !visited.insert(self.cells.clone()) {
+            return false;
+        }
+
+        for mv in self.moves_by_pair() {
+            let mut board = self.clone();
+            board.do_move(&mv);
+            moves.push(mv);
+            if board.solve_dfs(max_states, visited, moves) {
+                return true;
+            }
+            moves.pop();
+        }
+
+        false
     }
 
     /// 盤面上の全マスを列挙する。外周も含む。
@@ -148,11 +854,42 @@ impl Board {
         })
     }
 
+    /// 全マスの内容を行優先 (`r * ncol + c`) の順に並べたスライスを返す。外周も含む。
+    pub fn cells(&self) -> &[BoardCell] {
+        &self.cells
+    }
+
+    /// 全マスの内容をマス付きで列挙する。外周も含み、順序は `squares()` と同じ。
+    pub fn enumerate_cells(&self) -> impl Iterator<Item = (Square, BoardCell)> + '_ {
+        self.squares().zip(self.cells.iter().copied())
+    }
+
     /// 盤面上の全ての牌を列挙する。
     pub fn iter_tiles(&self) -> impl Iterator<Item = BoardCell> + '_ {
         self.enumerate_tiles().map(|e| e.1)
     }
 
+    /// 盤面上に残っている牌の数を種類ごとに数える。外周や空きマスは数えない。
+    pub fn kind_counts(&self) -> [usize; TILE_KIND_COUNT] {
+        let mut counts = [0; TILE_KIND_COUNT];
+
+        for tile in self.iter_tiles() {
+            if let BoardCell::Tile(kind) = tile {
+                counts[kind] += 1;
+            }
+        }
+
+        counts
+    }
+
+    /// 種類 `kind` の牌があるマスを全て返す。順序は `squares()` と同じ。
+    pub fn squares_of_kind(&self, kind: usize) -> Vec<Square> {
+        self.enumerate_tiles()
+            .filter(|&(_, tile)| tile == BoardCell::Tile(kind))
+            .map(|(sq, _)| sq)
+            .collect()
+    }
+
     /// 盤面上の全ての牌をマス付きで列挙する。
     pub fn enumerate_tiles(&self) -> impl Iterator<Item = (Square, BoardCell)> + '_ {
         self.squares_inner().filter_map(|sq| {
@@ -164,90 +901,591 @@ impl Board {
         })
     }
 
+    /// 着手の列を順に検証しながら行う。
+    ///
+    /// 各着手は `is_legal()` で検証する。不正な着手があれば、その位置 (0 始まり) を含むエラーを返し、
+    /// 盤面は変更しない。
+    pub fn apply_moves(&mut self, moves: &[Move]) -> anyhow::Result<()> {
+        let mut board = self.clone();
+
+        for (i, mv) in moves.iter().enumerate() {
+            ensure!(
+                board.is_legal(mv),
+                "move {i}: illegal move {}",
+                mv.to_string_repr()
+            );
+            board.do_move(mv);
+        }
+
+        *self = board;
+
+        Ok(())
+    }
+
+    /// (始点, 終点) の列を、順に着手したときの最短経路の着手の列に変換する。盤面は変更しない。
+    ///
+    /// 各着手は `shortest_move_between()` で求める。合法手がない組があれば、
+    /// その位置 (0 始まり) を含むエラーを返す。結果は `apply_moves()` で適用できる。
+    pub fn resolve_moves(&self, endpoints: &[(Square, Square)]) -> anyhow::Result<Vec<Move>> {
+        let mut board = self.clone();
+
+        endpoints
+            .iter()
+            .enumerate()
+            .map(|(i, &(src, dst))| {
+                let mv = board.shortest_move_between(src, dst).ok_or_else(|| {
+                    anyhow!(
+                        "move {i}: no legal move {},{}-{},{}",
+                        src.c,
+                        src.r,
+                        dst.c,
+                        dst.r
+                    )
+                })?;
+                board.do_move(&mv);
+                Ok(mv)
+            })
+            .collect()
+    }
+
     /// 着手を行う。`mv` は合法と仮定している。
+    ///
+    /// ルールで牌を寄せる方向が指定されている場合、残りの牌を寄せる。
     pub fn do_move(&mut self, mv: &Move) {
+        self.do_move_with_shifts(mv);
+    }
+
+    /// 着手を行い、牌を寄せた際の移動 (移動元, 移動先) を順に返す。
+    fn do_move_with_shifts(&mut self, mv: &Move) -> Vec<(Square, Square)> {
+        // マスの書き換えでキャッシュは破棄されるので、先に取り出しておく。
+        let move_pairs = self.move_pairs.get_mut().take();
+
         self[mv.src()] = BoardCell::Empty;
         self[mv.dst()] = BoardCell::Empty;
+
+        let shifts = self.gravity_shifts(mv);
+        for &(from, to) in &shifts {
+            self[to] = self[from];
+            self[from] = BoardCell::Empty;
+        }
+
+        // 牌が寄せられた場合は影響範囲が広いので、キャッシュは破棄したままにする。
+        if let Some(move_pairs) = move_pairs.filter(|_| shifts.is_empty()) {
+            let move_pairs = self.update_move_pairs(move_pairs, mv);
+            *self.move_pairs.get_mut() = Some(move_pairs);
+        }
+
+        shifts
+    }
+
+    /// 着手 `mv` (牌を寄せないもの) を行った後の盤面に合わせて、合法手を持つ牌の組を差分更新する。
+    fn update_move_pairs(
+        &self,
+        mut move_pairs: HashSet<(Square, Square)>,
+        mv: &Move,
+    ) -> HashSet<(Square, Square)> {
+        let removed = [mv.src(), mv.dst()];
+        move_pairs.retain(|(sq1, sq2)| !removed.contains(sq1) && !removed.contains(sq2));
+
+        // 牌を取っても既存の合法手の経路は塞がれない。
+        // 新たに合法手を持ち得るのは、空いたマスを経路が通り得る組のみ。
+        // 経路は両端の行/列と、両端の間の行/列のいずれかに沿うので、それ以外の組は調べなくてよい。
+        let may_pass = |sq1: Square, sq2: Square, sq: Square| {
+            let cs = sq1.c.min(sq2.c)..=sq1.c.max(sq2.c);
+            let rs = sq1.r.min(sq2.r)..=sq1.r.max(sq2.r);
+            cs.contains(&sq.c) || rs.contains(&sq.r)
+        };
+
+        for (sq1, sq2) in self.tile_pairs() {
+            if move_pairs.contains(&(sq1, sq2)) {
+                continue;
+            }
+            if removed.iter().any(|&sq| may_pass(sq1, sq2, sq)) && self.are_connectable(sq1, sq2) {
+                move_pairs.insert((sq1, sq2));
+            }
+        }
+
+        debug_assert_eq!(move_pairs, self.compute_move_pairs());
+
+        move_pairs
+    }
+
+    /// 合法手を持つ牌の組を全て調べて返す。
+    fn compute_move_pairs(&self) -> HashSet<(Square, Square)> {
+        self.tile_pairs()
+            .filter(|&(sq1, sq2)| self.are_connectable(sq1, sq2))
+            .collect()
+    }
+
+    /// 互いに取れる牌の組 (小さい方のマス, 大きい方のマス) を全て列挙する。
+    fn tile_pairs(&self) -> impl Iterator<Item = (Square, Square)> {
+        self.tiles_by_match_key().into_values().flat_map(|sqs| {
+            sqs.into_iter()
+                .tuple_combinations()
+                .map(|(sq1, sq2)| (sq1.min(sq2), sq1.max(sq2)))
+        })
+    }
+
+    /// `mv` の 2 マスを空けた後、残りの牌を寄せるための移動 (移動元, 移動先) を返す。
+    ///
+    /// 返される移動を順に適用すると、移動先は常に空いている。
+    fn gravity_shifts(&self, mv: &Move) -> Vec<(Square, Square)> {
+        // 牌を寄せる対象の列(または行)。各要素は寄せる先の側から並べる。
+        let lines: Vec<Vec<Square>> = match self.rule.gravity {
+            GravityMode::None => return Vec::new(),
+            GravityMode::Down | GravityMode::Up => {
+                let rs = 1..self.nrow.get() - 1;
+                [mv.src().c, mv.dst().c]
+                    .into_iter()
+                    .dedup()
+                    .map(|c| {
+                        let line = rs.clone().map(move |r| Square::new(c, r));
+                        if self.rule.gravity == GravityMode::Down {
+                            line.rev().collect()
+                        } else {
+                            line.collect()
+                        }
+                    })
+                    .collect()
+            }
+            GravityMode::Left | GravityMode::Right => {
+                let cs = 1..self.ncol.get() - 1;
+                [mv.src().r, mv.dst().r]
+                    .into_iter()
+                    .dedup()
+                    .map(|r| {
+                        let line = cs.clone().map(move |c| Square::new(c, r));
+                        if self.rule.gravity == GravityMode::Right {
+                            line.rev().collect()
+                        } else {
+                            line.collect()
+                        }
+                    })
+                    .collect()
+            }
+        };
+
+        // 牌を置けないマスは動かず、牌はその手前で止まる。
+        let mut shifts = Vec::<(Square, Square)>::new();
+        for line in lines {
+            for segment in line.split(|&sq| self[sq].is_blocked()) {
+                let mut tos = segment.iter().copied();
+                for &from in segment.iter().filter(|&&sq| self[sq].is_tile()) {
+                    let to = tos.next().expect("segment should have enough squares");
+                    if from != to {
+                        shifts.push((from, to));
+                    }
+                }
+            }
+        }
+
+        shifts
     }
 
     /// 盤面上の全ての牌について、位置を変えずにシャッフルする。
     /// 結果の盤面は解を持つことが保証される。
-    pub fn shuffle_solvable(&mut self) {
+    ///
+    /// 牌を置けないマスに阻まれ、種類を無視しても経路で結べる牌の組がなくなる場合など、
+    /// 解のある配置が見つからなければエラーを返し、盤面は変更しない。
+    /// 具体的には、シャッフルしても 1 手も着手できないとき、どう並べても着手できない状態なら
+    /// すぐに、そうでなければ `SHUFFLE_MAX_STALLS` 回続いたら諦める。
+    /// 牌を置けないマスがない盤面では、最も近い牌同士が常に結べるので前者は起こらず、
+    /// 後者も事実上起こらない (各シャッフルで一定以上の確率で着手できる)。
+    pub fn shuffle_solvable(&mut self) -> anyhow::Result<()> {
+        self.shuffle_with_rng(&mut new_rng())
+    }
+
+    /// 乱数生成器を指定して `shuffle_solvable()` を行う。
+    ///
+    /// 同じ盤面と同じ状態の乱数生成器からは同じ結果が得られる。
+    pub fn shuffle_with_rng(&mut self, rng: &mut impl Rng) -> anyhow::Result<()> {
         // シャッフルしてから合法手がなくなるまでランダムな着手を続ける。
         // これを盤面が空になるまで繰り返す。
 
         // 作業はコピーした盤面上で行い、シャッフル結果を self に書き戻す。
+        // 牌を寄せるルールでは作業中に牌が移動するので、各牌の元の位置を追跡する。
+        let original = self.clone();
         let mut board = self.clone();
+        let mut origins: Vec<Square> = self.squares().collect();
+        let mut stalls = 0;
 
-        while !board.is_empty() {
-            board.shuffle();
+        // 作業用のバッファは繰り返しの間で使い回す。
+        let mut sqs = Vec::<Square>::new();
+        let mut tiles = Vec::<BoardCell>::new();
+        let mut pairs = Vec::<(Square, Square)>::new();
 
+        while !board.is_empty() {
+            // 位置を変えずに牌をシャッフルし、self にも書き戻す。
+            sqs.clear();
+            tiles.clear();
             for (sq, tile) in board.enumerate_tiles() {
-                self[sq] = tile;
+                sqs.push(sq);
+                tiles.push(tile);
+            }
+            tiles.shuffle(rng);
+            for (&sq, &tile) in std::iter::zip(&sqs, &tiles) {
+                board[sq] = tile;
+                self[origins[board.sq2idx(sq)]] = tile;
             }
 
-            while let Some(mv) = board.random_move() {
-                board.do_move(&mv);
+            // 互いに取れる牌の組をランダムな順に調べ、合法手がなくなるまで着手を続ける。
+            // 組の一覧は取った牌を含むものを除いて使い回し、牌が寄せられた場合のみ作り直す。
+            pairs.clear();
+            pairs.extend(board.tile_pairs());
+            pairs.shuffle(rng);
+
+            let mut moved = false;
+            while let Some((i, mv)) = pairs
+                .iter()
+                .enumerate()
+                .find_map(|(i, &(sq1, sq2))| board.find_move_between(sq1, sq2).map(|mv| (i, mv)))
+            {
+                moved = true;
+                pairs.swap_remove(i);
+
+                let shifts = board.do_move_with_shifts(&mv);
+                for &(from, to) in &shifts {
+                    let origin = origins[board.sq2idx(from)];
+                    let idx = board.sq2idx(to);
+                    origins[idx] = origin;
+                }
+
+                if shifts.is_empty() {
+                    let removed = [mv.src(), mv.dst()];
+                    pairs.retain(|(sq1, sq2)| !removed.contains(sq1) && !removed.contains(sq2));
+                } else {
+                    pairs.clear();
+                    pairs.extend(board.tile_pairs());
+                    pairs.shuffle(rng);
+                }
+            }
+
+            // 1 手も着手できなかった場合、どう並べても着手できないならすぐに諦める。
+            // 着手しない限り牌の位置は変わらないので、これは最初の 1 回だけ調べればよい。
+            if moved {
+                stalls = 0;
+            } else {
+                stalls += 1;
+                if stalls == 1 && !board.has_connectable_squares() {
+                    *self = original;
+                    bail!("shuffle: no pair of remaining tiles can be connected");
+                }
+                if stalls >= SHUFFLE_MAX_STALLS {
+                    *self = original;
+                    bail!("shuffle: no move after {SHUFFLE_MAX_STALLS} shuffles");
+                }
             }
         }
-    }
 
-    /// 盤面上の全ての牌について、位置を変えずにシャッフルする。
-    /// 結果の盤面は解を持つとは限らない。
-    fn shuffle(&mut self) {
-        let mut tiles: Vec<_> = self.iter_tiles().collect();
-        tiles.shuffle(&mut thread_rng());
+        Ok(())
+    }
 
-        // 逆順になるが、どうせシャッフルしてるので問題ない。
+    /// 牌の種類を無視したとき、経路で結べる牌の組があるかどうかを返す。
+    ///
+    /// false なら、牌をどう並べ替えても着手できない。
+    fn has_connectable_squares(&self) -> bool {
+        let mut board = self.clone();
+        board.rule.groups.clear();
         for sq in self.squares_inner() {
-            if self[sq].is_tile() {
-                self[sq] = tiles.pop().expect("tiles should be nonempty");
+            if board[sq].is_tile() {
+                board[sq] = BoardCell::Tile(0);
             }
         }
+
+        board.find_move().is_some()
     }
 
-    /// 現在の盤面における合法手を 0 または 1 個返す。単純な全探索による。
+    /// 現在の盤面における合法手を 0 または 1 個返す。
+    ///
+    /// 互いに取れる牌同士に分けた上で、その中の全ての組を調べる。
     pub fn find_move(&self) -> Option<Move> {
-        self.squares_inner()
-            .combinations(2)
-            .flat_map(|sqs| self.find_move_between(sqs[0], sqs[1]))
-            .next()
+        self.moves_by_pair().next()
+    }
+
+    /// 現在の盤面における合法手の数を返す。
+    ///
+    /// 牌の組 (順序は問わない) ごとに数えるので、`all_moves().len()` に等しい。
+    /// 初回の呼び出しで全ての組を調べ、以降は着手ごとに差分更新した結果を使う。
+    pub fn count_moves(&self) -> usize {
+        let mut move_pairs = self.move_pairs.borrow_mut();
+
+        move_pairs
+            .get_or_insert_with(|| self.compute_move_pairs())
+            .len()
+    }
+
+    /// 現在の盤面における全ての合法手を返す。
+    ///
+    /// 牌の組 (順序は問わない) ごとに、最短経路の着手を 1 個ずつ返す。
+    pub fn all_moves(&self) -> Vec<Move> {
+        self.tiles_by_match_key()
+            .into_values()
+            .flat_map(|sqs| {
+                sqs.into_iter()
+                    .tuple_combinations()
+                    .flat_map(|(sq1, sq2)| self.shortest_move_between(sq1, sq2))
+            })
+            .collect()
+    }
+
+    /// 種類 `kind` の牌同士の全ての合法手を返す。
+    ///
+    /// `all_moves()` と同様、牌の組 (順序は問わない) ごとに最短経路の着手を 1 個ずつ返す。
+    /// グループに属する他の種類の牌との着手は含まない。
+    pub fn moves_of_kind(&self, kind: usize) -> Vec<Move> {
+        self.squares_of_kind(kind)
+            .into_iter()
+            .tuple_combinations()
+            .flat_map(|(sq1, sq2)| self.shortest_move_between(sq1, sq2))
+            .collect()
+    }
+
+    /// 互いに取れる牌同士だが、経路がなく取れない組を全て返す。
+    ///
+    /// 手詰まりの盤面では、残っている組のうちどれが経路に阻まれているかを示すのに使う。
+    pub fn blocked_pairs(&self) -> Vec<(Square, Square)> {
+        self.tiles_by_match_key()
+            .into_values()
+            .flat_map(|sqs| {
+                sqs.into_iter()
+                    .tuple_combinations()
+                    .filter(|&(sq1, sq2)| !self.are_connectable(sq1, sq2))
+            })
+            .collect()
+    }
+
+    /// 着手後も手詰まりにならない合法手を 0 または 1 個返す。
+    ///
+    /// 各合法手について、着手後に `SAFE_MOVE_LOOKAHEAD` 手先まで手詰まりにならない手順があるかを調べる。
+    /// 計算量は (合法手の数)^(`SAFE_MOVE_LOOKAHEAD` + 1) 程度になるので、先読みの深さは小さく抑えている。
+    /// そのような合法手がない場合、任意の合法手を返す。
+    pub fn safe_move(&self) -> Option<Move> {
+        let mut fallback = None;
+
+        for mv in self.moves_by_pair() {
+            let mut board = self.clone();
+            board.do_move(&mv);
+            if board.survives(SAFE_MOVE_LOOKAHEAD) {
+                return Some(mv);
+            }
+            fallback.get_or_insert(mv);
+        }
+
+        fallback
+    }
+
+    /// 盤面が空になるか、`depth` 手先まで手詰まりにならない手順があるかどうかを返す。
+    fn survives(&self, depth: usize) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        if depth == 0 {
+            return !self.is_stuck();
+        }
+
+        self.moves_by_pair().any(|mv| {
+            let mut board = self.clone();
+            board.do_move(&mv);
+            board.survives(depth - 1)
+        })
+    }
+
+    /// 互いに取れる牌の組ごとに、合法手を 0 または 1 個ずつ列挙する。
+    fn moves_by_pair(&self) -> impl Iterator<Item = Move> + '_ {
+        self.tiles_by_match_key()
+            .into_values()
+            .flat_map(move |sqs| {
+                sqs.into_iter()
+                    .tuple_combinations()
+                    .flat_map(move |(sq1, sq2)| self.find_move_between(sq1, sq2))
+            })
+    }
+
+    /// 盤面上の牌のマスを、互いに取れる牌同士ごとにまとめて返す。
+    ///
+    /// キーは牌種(グループに属する牌種の場合、そのグループの最小の牌種)。
+    /// `HashMap` だとプロセスごとにイテレート順が変わり、`find_move()` などの結果やシードからの
+    /// 生成が再現しなくなるので、キーの順にイテレートする `BTreeMap` を使う。
+    fn tiles_by_match_key(&self) -> BTreeMap<usize, Vec<Square>> {
+        let mut buckets = BTreeMap::<usize, Vec<Square>>::new();
+
+        for (sq, tile) in self.enumerate_tiles() {
+            if let BoardCell::Tile(kind) = tile {
+                let key = self.group_of(kind).map_or(kind, |group| group.kinds()[0]);
+                buckets.entry(key).or_default().push(sq);
+            }
+        }
+
+        buckets
     }
 
     /// 現在の盤面におけるランダムな合法手を 0 または 1 個返す。
+    ///
+    /// 互いに取れる牌同士の組をランダムな順に 1 つずつ取り出して調べ、最初に見つかった合法手を返す。
+    /// 合法手を持つ組はいずれも等確率で選ばれる。
     pub fn random_move(&self) -> Option<Move> {
-        let mut combs: Vec<_> = self.squares_inner().combinations(2).collect();
-        combs.shuffle(&mut thread_rng());
+        self.random_move_with_rng(&mut new_rng())
+    }
 
-        combs
-            .into_iter()
-            .flat_map(|sqs| self.find_move_between(sqs[0], sqs[1]))
-            .next()
+    /// 乱数生成器を指定して `random_move()` を行う。
+    pub fn random_move_with_rng(&self, rng: &mut impl Rng) -> Option<Move> {
+        self.random_move_with_probes(rng).0
+    }
+
+    /// `random_move()` の本体。合法手と、それが見つかるまでに調べた組の数を返す。
+    ///
+    /// 組の一覧は作らず、組の番号を部分的な Fisher-Yates シャッフルで重複なく取り出す。
+    /// 入れ替えた番号のみを記録するので、メモリは調べた組の数に比例する。
+    fn random_move_with_probes(&self, rng: &mut impl Rng) -> (Option<Move>, usize) {
+        let buckets: Vec<Vec<Square>> = self
+            .tiles_by_match_key()
+            .into_values()
+            .filter(|sqs| sqs.len() >= 2)
+            .collect();
+        // 各グループの組の数と、組の番号の開始位置。
+        let counts: Vec<usize> = buckets
+            .iter()
+            .map(|sqs| sqs.len() * (sqs.len() - 1) / 2)
+            .collect();
+        let starts: Vec<usize> = counts
+            .iter()
+            .scan(0, |acc, &count| {
+                let start = *acc;
+                *acc += count;
+                Some(start)
+            })
+            .collect();
+        let total: usize = counts.iter().sum();
+
+        // 組の番号に対応する 2 マスを返す。
+        let pair_at = |idx: usize| -> (Square, Square) {
+            let bucket = starts.partition_point(|&start| start <= idx) - 1;
+            let sqs = &buckets[bucket];
+            let mut k = idx - starts[bucket];
+            let mut i = 0;
+            while k >= sqs.len() - 1 - i {
+                k -= sqs.len() - 1 - i;
+                i += 1;
+            }
+            (sqs[i], sqs[i + 1 + k])
+        };
+
+        let mut swapped = HashMap::<usize, usize>::new();
+        for i in 0..total {
+            let j = rng.gen_range(i..total);
+            let idx = swapped.get(&j).copied().unwrap_or(j);
+            swapped.insert(j, swapped.get(&i).copied().unwrap_or(i));
+
+            let (sq1, sq2) = pair_at(idx);
+            if let Some(mv) = self.find_move_between(sq1, sq2) {
+                return (Some(mv), i + 1);
+            }
+        }
+
+        (None, total)
     }
 
     /// 指定した 2 マスに対する合法手を 0 または 1 個返す。
+    ///
+    /// 取れるかどうかは引数の順序によらない。`(src, dst)` で合法手が見つかれば
+    /// `(dst, src)` でも見つかるが、返る経路は一致するとは限らない。
+    /// 引数の順序によらず同じ着手が必要なら `find_move_between_normalized()` を使う。
     pub fn find_move_between(&self, src: Square, dst: Square) -> Option<Move> {
         self.moves_between(src, dst).next()
     }
 
+    /// 指定した 2 マスに対する合法手を、`Move::normalized()` の向きで 0 または 1 個返す。
+    ///
+    /// `find_move_between()` と異なり、引数の順序によらず同じ着手を返す。
+    pub fn find_move_between_normalized(&self, sq1: Square, sq2: Square) -> Option<Move> {
+        let (src, dst) = if sq1 <= sq2 { (sq1, sq2) } else { (sq2, sq1) };
+
+        self.find_move_between(src, dst)
+    }
+
+    /// 指定した 2 マスの牌を取れるかどうかを返す。引数の順序によらない。
+    ///
+    /// 同じマスや、互いに取れる牌同士でない場合は false を返す。
+    pub fn are_connectable(&self, sq1: Square, sq2: Square) -> bool {
+        self.find_move_between(sq1, sq2).is_some()
+    }
+
     /// 指定した 2 マスに対する最短経路の合法手を 0 または 1 個返す。
+    ///
+    /// 最短経路の長さは引数の順序によらない。
+    /// 最短経路が複数ある場合、`Move::cmp_preference()` で最も優先されるものを返す。
     pub fn shortest_move_between(&self, src: Square, dst: Square) -> Option<Move> {
         self.moves_between(src, dst)
-            .min_by_key(|mv| mv.path_distance())
+            .min_by(|mv1, mv2| mv1.cmp_preference(mv2))
+    }
+
+    /// 着手 `mv` が現在の盤面で合法かどうかを返す。
+    ///
+    /// `find_move_between()` と異なり、与えられた経路そのものを検証する。
+    /// 始点と終点が異なり互いに取れる牌同士であること、経路が 1 ～ 3 本の縦または横の
+    /// 線分 (長さ 0 は不可) からなり盤面内 (外周を使えないルールでは外周を除いた範囲) に
+    /// 収まること、経路上の両端以外のマスが全て空であることを確かめる。
+    pub fn is_legal(&self, mv: &Move) -> bool {
+        let path = mv.path();
+
+        if !(2..=4).contains(&path.len()) {
+            return false;
+        }
+
+        let ncol = self.ncol.get();
+        let nrow = self.nrow.get();
+        let (c_range, r_range) = if self.rule.allow_edge_routing {
+            (0..ncol, 0..nrow)
+        } else {
+            (1..ncol - 1, 1..nrow - 1)
+        };
+        if !path
+            .iter()
+            .all(|sq| c_range.contains(&sq.c) && r_range.contains(&sq.r))
+        {
+            return false;
+        }
+
+        let src = mv.src();
+        let dst = mv.dst();
+        if src == dst || !self.is_matching(self[src], self[dst]) {
+            return false;
+        }
+
+        // 始点を除き、経路が通るマスを順に列挙する。最後のマスは終点となる。
+        let mut passed = Vec::<Square>::with_capacity(mv.distance());
+        for e in path.windows(2) {
+            let (sq1, sq2) = (e[0], e[1]);
+            if sq1 == sq2 || (sq1.c != sq2.c && sq1.r != sq2.r) {
+                return false;
+            }
+            let mut sq = sq1;
+            while sq != sq2 {
+                sq = Square::new(step_toward(sq.c, sq2.c), step_toward(sq.r, sq2.r));
+                passed.push(sq);
+            }
+        }
+        passed.pop();
+
+        // 途中で始点や終点を通る場合もここで弾かれる。
+        passed.into_iter().all(|sq| self[sq].is_empty())
     }
 
     /// 指定した 2 マスに対する合法手(全ての経路)を列挙する。
     fn moves_between(&self, src: Square, dst: Square) -> impl Iterator<Item = Move> + '_ {
         // src, dst が同一なら違法。
-        // src, dst の牌種が異なるなら違法。
-        if src == dst || !self[src].is_same_tile(self[dst]) {
+        // src, dst が互いに取れる牌同士でないなら違法。
+        if src == dst || !self.is_matching(self[src], self[dst]) {
             return Either::Left(std::iter::empty());
         }
 
         // 二角取りの経路は、縦-横-縦 または 横-縦-横 のいずれか。
+        // 生成した経路は線分 3 本 (曲がり 2 回) 以内のはずなので、デバッグビルドでは
+        // `is_legal()` で検証する。外周を 2 辺にわたって回り込む経路などが混入すれば検出できる。
         Either::Right(
             self.moves_between_vhv(src, dst)
-                .chain(self.moves_between_hvh(src, dst)),
+                .chain(self.moves_between_hvh(src, dst))
+                .inspect(|mv| debug_assert!(self.is_legal(mv), "illegal move generated: {mv:?}")),
         )
     }
 
@@ -262,19 +1500,24 @@ impl Board {
             let f_min = |sq: Square| {
                 (0..sq.r)
                     .rev()
-                    .find(|&r| self[Square::new(sq.c, r)].is_tile())
+                    .find(|&r| !self[Square::new(sq.c, r)].is_empty())
                     .map(|r| r + 1)
                     .unwrap_or(0)
             };
             let f_max = |sq: Square| {
                 (sq.r + 1..self.nrow.get())
-                    .find(|&r| self[Square::new(sq.c, r)].is_tile())
+                    .find(|&r| !self[Square::new(sq.c, r)].is_empty())
                     .map(|r| r - 1)
                     .unwrap_or(self.nrow.get() - 1)
             };
             let range_src = f_min(src)..=f_max(src);
             let range_dst = f_min(dst)..=f_max(dst);
-            util::range_intersection(range_src, range_dst)
+            let range = util::range_intersection(range_src, range_dst);
+            if self.rule.allow_edge_routing {
+                range
+            } else {
+                util::range_intersection(range, 1..=self.nrow.get() - 2)
+            }
         };
 
         let c_range = {
@@ -283,6 +1526,10 @@ impl Board {
             min..=max
         };
 
+        if util::is_empty_range(&r_range) {
+            return Either::Left(std::iter::empty());
+        }
+
         Either::Right(
             r_range
                 .filter(move |&r| c_range.clone().all(|c| self[Square::new(c, r)].is_empty()))
@@ -301,19 +1548,24 @@ impl Board {
             let f_min = |sq: Square| {
                 (0..sq.c)
                     .rev()
-                    .find(|&c| self[Square::new(c, sq.r)].is_tile())
+                    .find(|&c| !self[Square::new(c, sq.r)].is_empty())
                     .map(|c| c + 1)
                     .unwrap_or(0)
             };
             let f_max = |sq: Square| {
                 (sq.c + 1..self.ncol.get())
-                    .find(|&c| self[Square::new(c, sq.r)].is_tile())
+                    .find(|&c| !self[Square::new(c, sq.r)].is_empty())
                     .map(|c| c - 1)
                     .unwrap_or(self.ncol.get() - 1)
             };
             let range_src = f_min(src)..=f_max(src);
             let range_dst = f_min(dst)..=f_max(dst);
-            util::range_intersection(range_src, range_dst)
+            let range = util::range_intersection(range_src, range_dst);
+            if self.rule.allow_edge_routing {
+                range
+            } else {
+                util::range_intersection(range, 1..=self.ncol.get() - 2)
+            }
         };
 
         let r_range = {
@@ -322,6 +1574,10 @@ impl Board {
             min..=max
         };
 
+        if util::is_empty_range(&c_range) {
+            return Either::Left(std::iter::empty());
+        }
+
         Either::Right(
             c_range
                 .filter(move |&c| r_range.clone().all(|r| self[Square::new(c, r)].is_empty()))
@@ -338,6 +1594,15 @@ impl Board {
     }
 }
 
+/// `x` を `target` に向けて 1 だけ進めた値を返す。等しければそのまま返す。
+fn step_toward(x: usize, target: usize) -> usize {
+    match x.cmp(&target) {
+        std::cmp::Ordering::Less => x + 1,
+        std::cmp::Ordering::Equal => x,
+        std::cmp::Ordering::Greater => x - 1,
+    }
+}
+
 impl std::ops::Index<Square> for Board {
     type Output = BoardCell;
 
@@ -349,13 +1614,17 @@ impl std::ops::Index<Square> for Board {
 
 impl std::ops::IndexMut<Square> for Board {
     fn index_mut(&mut self, sq: Square) -> &mut Self::Output {
+        *self.move_pairs.get_mut() = None;
+
         let idx = self.sq2idx(sq);
         &mut self.cells[idx]
     }
 }
 
 /// 着手。
-#[derive(Debug)]
+///
+/// 等価性は経路の向きも含めて比較する。向きを無視する場合は `normalized()` を使う。
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct Move {
     path: Vec<Square>,
 }
@@ -418,8 +1687,56 @@ impl Move {
         &self.path
     }
 
+    /// 始点が終点以下になるよう向きを揃えた着手を返す。
+    ///
+    /// 始点と終点を入れ替えただけの着手同士は、これにより等しくなる。
+    pub fn normalized(&self) -> Self {
+        if self.src() <= self.dst() {
+            self.clone()
+        } else {
+            Self {
+                path: self.path.iter().rev().copied().collect(),
+            }
+        }
+    }
+
+    /// 着手の文字列表現 `{src.c},{src.r}-{dst.c},{dst.r}` を返す。経路は含まない。
+    pub fn to_string_repr(&self) -> String {
+        let src = self.src();
+        let dst = self.dst();
+
+        format!("{},{}-{},{}", src.c, src.r, dst.c, dst.r)
+    }
+
+    /// 着手の文字列表現から (始点, 終点) を復元する。
+    ///
+    /// 経路は盤面に依存するので復元しない。形式は `to_string_repr()` を参照。
+    pub fn parse_endpoints(s: &str) -> anyhow::Result<(Square, Square)> {
+        let parse_square = |s: &str| -> anyhow::Result<Square> {
+            let (c, r) = s
+                .split_once(',')
+                .ok_or_else(|| anyhow!("move repr: missing ',' in '{s}'"))?;
+            let c = c
+                .parse()
+                .with_context(|| format!("move repr: invalid column '{c}'"))?;
+            let r = r
+                .parse()
+                .with_context(|| format!("move repr: invalid row '{r}'"))?;
+            Ok(Square::new(c, r))
+        };
+
+        let (src, dst) = s
+            .split_once('-')
+            .ok_or_else(|| anyhow!("move repr: missing '-' in '{s}'"))?;
+
+        Ok((parse_square(src)?, parse_square(dst)?))
+    }
+
     /// 経路長を返す。
-    fn path_distance(&self) -> usize {
+    ///
+    /// 各線分の長さをチェビシェフ距離 (列と行の差の大きい方) で測り、その総和を返す。
+    /// 線分は縦または横なので、これは線分が通るマスの数に等しい。
+    pub fn distance(&self) -> usize {
         self.path
             .windows(2)
             .map(|e| <&[Square; 2]>::try_from(e).expect("window length should be 2"))
@@ -430,4 +1747,525 @@ impl Move {
             })
             .sum()
     }
+
+    /// 曲がる回数を返す。
+    pub fn turns(&self) -> usize {
+        self.path.len() - 2
+    }
+
+    /// 経路が縦の線分から始まるかどうかを返す。
+    fn starts_vertical(&self) -> bool {
+        self.path[0].c == self.path[1].c
+    }
+
+    /// 同じ始点と終点を持つ着手同士の優先順位を比較する。優先される方が小さい。
+    ///
+    /// 経路長が短い方、曲がる回数が少ない方、縦の線分から始まる方の順に優先し、
+    /// それでも決まらなければ曲がり角のマスを始点側から `Square` の順序で比べる。
+    /// 経路が異なれば等しくならないので、同じ始点と終点を持つ着手の全順序になる。
+    pub fn cmp_preference(&self, other: &Self) -> std::cmp::Ordering {
+        self.distance()
+            .cmp(&other.distance())
+            .then_with(|| self.turns().cmp(&other.turns()))
+            .then_with(|| other.starts_vertical().cmp(&self.starts_vertical()))
+            .then_with(|| self.path.cmp(&other.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nz(n: usize) -> NonZeroUsize {
+        NonZeroUsize::new(n).unwrap()
+    }
+
+    #[test]
+    fn random_move_probes_fewer_pairs_than_enumeration() {
+        // 牌種を減らし、互いに取れる牌同士の組を多くする。
+        let rule = Rule {
+            tile_kinds: 4,
+            ..Default::default()
+        };
+        let mut rng = BoardRng::seed_from_u64(0);
+        let board = Board::generate(nz(12), nz(7), rule, |_| None, &mut rng).unwrap();
+
+        // 以前の実装は、毎回互いに取れる牌同士の組を全て列挙してシャッフルしていた。
+        let pair_count = board.tile_pairs().count();
+        let probes: usize = (0..100)
+            .map(|_| {
+                let (mv, probes) = board.random_move_with_probes(&mut rng);
+                assert!(board.is_legal(&mv.unwrap()));
+                probes
+            })
+            .sum();
+
+        assert!(probes < 100 * pair_count / 4, "{probes} vs {pair_count}");
+    }
+
+    #[test]
+    fn random_move_covers_all_moves() {
+        let mut rng = BoardRng::seed_from_u64(1);
+        let board = Board::random_with_rng(nz(6), nz(9), &mut rng);
+
+        let picked: HashSet<Move> = (0..2000)
+            .map(|_| board.random_move_with_probes(&mut rng).0.unwrap())
+            .map(|mv| mv.normalized())
+            .collect();
+        let picked: HashSet<(Square, Square)> =
+            picked.iter().map(|mv| (mv.src(), mv.dst())).collect();
+
+        assert_eq!(picked.len(), board.count_moves());
+    }
+
+    #[test]
+    fn random_move_on_stuck_board() {
+        let board = Board::from_string_repr("2x2:0110", Rule::default()).unwrap();
+        let mut rng = BoardRng::seed_from_u64(0);
+
+        assert_eq!(board.random_move_with_probes(&mut rng), (None, 2));
+    }
+
+    #[test]
+    fn random_tiles_balanced_across_units() {
+        let rules = [
+            Rule::default(),
+            Rule {
+                tile_kinds: 5,
+                ..Default::default()
+            },
+            Rule {
+                groups: vec![TileGroup::new(30..34)],
+                ..Default::default()
+            },
+        ];
+        let mut rng = BoardRng::seed_from_u64(0);
+
+        for rule in rules {
+            let board = Board::random_with_rule(nz(2), nz(1), rule);
+            // 組の単位 (グループに属さない牌種、またはグループ) ごとのキー。
+            let key = |kind: usize| board.group_of(kind).map_or(kind, |group| group.kinds()[0]);
+            let keys: Vec<usize> = (0..board.rule.tile_kinds).map(key).unique().collect();
+
+            for n_pair in [1, 7, 27, 34, 42, 100] {
+                let tiles = board.random_tiles(n_pair, &mut rng);
+                assert_eq!(tiles.len(), 2 * n_pair);
+
+                let counts = tiles.iter().map(|&kind| key(kind)).counts();
+                assert!(counts.values().all(|&count| count % 2 == 0));
+
+                let pair_counts = keys.iter().map(|k| counts.get(k).copied().unwrap_or(0) / 2);
+                let (min, max) = pair_counts.minmax().into_option().unwrap();
+                assert!(max - min <= 1, "n_pair={n_pair}: {min}..={max}");
+            }
+        }
+    }
+
+    #[test]
+    fn random_symmetric_is_symmetric() {
+        for (ncol, nrow) in [(6, 9), (5, 8), (7, 4), (6, 8), (4, 4)] {
+            for symmetry in [Symmetry::Mirror, Symmetry::Point] {
+                let board = Board::random_symmetric(nz(ncol), nz(nrow), symmetry);
+
+                assert_eq!(board.tile_count(), ncol * nrow);
+                for sq in board.squares() {
+                    let image = board.symmetric_square(sq, symmetry);
+                    assert_eq!(board[sq], board[image], "{symmetry:?} {sq:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn random_symmetric_point_even_center() {
+        // 縦横とも偶数の点対称では、中央の 2x2 マスが全て同じ牌になる。
+        for (ncol, nrow) in [(2, 2), (4, 4), (6, 8)] {
+            let board = Board::random_symmetric(nz(ncol), nz(nrow), Symmetry::Point);
+            let c = board.ncol().get() / 2;
+            let r = board.nrow().get() / 2;
+
+            let tile = board[Square::new(c - 1, r - 1)];
+            assert!(tile.is_tile());
+            for sq in [
+                Square::new(c, r - 1),
+                Square::new(c - 1, r),
+                Square::new(c, r),
+            ] {
+                assert_eq!(board[sq], tile);
+            }
+            assert!(board.is_solvable());
+        }
+    }
+
+    #[test]
+    fn random_symmetric_with_rule_keeps_rule() {
+        let rule = Rule {
+            gravity: GravityMode::Down,
+            tile_kinds: 5,
+            ..Default::default()
+        };
+        let board = Board::random_symmetric_with_rule(nz(6), nz(5), rule, Symmetry::Mirror);
+
+        assert_eq!(board.rule().gravity, GravityMode::Down);
+        assert!(board
+            .iter_tiles()
+            .all(|tile| matches!(tile, BoardCell::Tile(kind) if kind < 5)));
+        for sq in board.squares() {
+            assert_eq!(
+                board[sq],
+                board[board.symmetric_square(sq, Symmetry::Mirror)]
+            );
+        }
+        assert!(board.is_solvable());
+    }
+
+    #[test]
+    fn move_pairs_cache_through_full_game() {
+        for gravity in [GravityMode::None, GravityMode::Down, GravityMode::Left] {
+            let rule = Rule {
+                gravity,
+                ..Default::default()
+            };
+            let mut board = Board::random_with_rule(nz(6), nz(9), rule);
+            let moves = board.solve().unwrap();
+
+            for mv in &moves {
+                // キャッシュを作っておき、着手時に差分更新 (と debug_assert による全体の再計算との比較) をさせる。
+                board.count_moves();
+                board.do_move(mv);
+
+                if gravity == GravityMode::None {
+                    assert!(board.move_pairs.borrow().is_some());
+                }
+                assert_eq!(board.count_moves(), board.compute_move_pairs().len());
+            }
+            assert!(board.is_empty());
+        }
+    }
+
+    #[test]
+    fn shuffle_solvable_keeps_kinds_and_solvability() {
+        let mut rng = BoardRng::seed_from_u64(0);
+
+        for gravity in [GravityMode::None, GravityMode::Down] {
+            let rule = Rule {
+                gravity,
+                ..Default::default()
+            };
+            let mut board = Board::random_with_rule(nz(6), nz(9), rule);
+            // 途中まで進めた盤面もシャッフルできる。
+            for mv in &board.solve().unwrap()[..5] {
+                board.do_move(mv);
+            }
+
+            for _ in 0..5 {
+                let before = board.clone();
+                board.shuffle_with_rng(&mut rng).unwrap();
+
+                assert_eq!(board.kind_counts(), before.kind_counts());
+                assert!(board
+                    .squares()
+                    .all(|sq| board[sq].is_tile() == before[sq].is_tile()));
+                assert!(board.is_solvable());
+            }
+        }
+    }
+
+    #[test]
+    fn random_seeded_is_pinned() {
+        // rand を更新して乱数列が変わったらここで検出される。
+        let board = Board::random_seeded(nz(6), nz(9), 0);
+        assert_eq!(
+            board.to_string_repr(),
+            "6x9:vcexx816eghwfs9kthva42wnabbp96utjjm4dcpksndg0551umf028"
+        );
+        assert_eq!(
+            board.to_string_repr(),
+            Board::random_seeded(nz(6), nz(9), 0).to_string_repr()
+        );
+    }
+
+    #[test]
+    fn random_move_with_rng_is_deterministic() {
+        let board = Board::random_seeded(nz(6), nz(9), 0);
+        let mv = |seed| board.random_move_with_rng(&mut BoardRng::seed_from_u64(seed));
+
+        assert_eq!(mv(0), mv(0));
+        assert!(board.is_legal(&mv(0).unwrap()));
+    }
+
+    #[test]
+    fn squares_of_kind_in_square_order() {
+        let board = Board::from_string_repr("3x2:0.11.0", Rule::default()).unwrap();
+
+        assert_eq!(
+            board.squares_of_kind(0),
+            [Square::new(1, 1), Square::new(3, 2)]
+        );
+        assert_eq!(
+            board.squares_of_kind(1),
+            [Square::new(3, 1), Square::new(1, 2)]
+        );
+        // 盤面にない牌種は空。
+        assert!(board.squares_of_kind(2).is_empty());
+    }
+
+    #[test]
+    fn find_move_between_normalized_ignores_argument_order() {
+        // 斜めに置いた牌同士は複数の経路で取れる。
+        let board = Board::from_string_repr("3x3:0.......0", Rule::default()).unwrap();
+        let sq1 = Square::new(1, 1);
+        let sq2 = Square::new(3, 3);
+
+        let mv = board.find_move_between_normalized(sq1, sq2).unwrap();
+        assert_eq!(
+            board.find_move_between_normalized(sq2, sq1),
+            Some(mv.clone())
+        );
+        assert_eq!((mv.src(), mv.dst()), (sq1, sq2));
+        assert_eq!(mv, mv.normalized());
+    }
+
+    #[test]
+    fn is_legal_checks_kind_path_and_turns() {
+        let board = Board::from_string_repr("4x1:0110", Rule::default()).unwrap();
+        let mv = |path: &[(usize, usize)]| Move {
+            path: path.iter().map(|&(c, r)| Square::new(c, r)).collect(),
+        };
+
+        // 隣接する同種の牌。
+        assert!(board.is_legal(&mv(&[(2, 1), (3, 1)])));
+        // 外周を回る 2 回曲がりの経路。
+        assert!(board.is_legal(&mv(&[(1, 1), (1, 0), (4, 0), (4, 1)])));
+
+        // 牌種が異なる。
+        assert!(!board.is_legal(&mv(&[(1, 1), (2, 1)])));
+        // 経路が牌に阻まれている。
+        assert!(!board.is_legal(&mv(&[(1, 1), (4, 1)])));
+        // 経路上のマスは全て空だが、3 回曲がっている。
+        assert!(!board.is_legal(&mv(&[(1, 1), (0, 1), (0, 0), (4, 0), (4, 1)])));
+    }
+
+    #[test]
+    fn from_kinds_validates_input() {
+        let board = Board::from_kinds(nz(2), nz(2), &[Some(0), None, None, Some(0)]).unwrap();
+        assert_eq!(board.to_string_repr(), "2x2:0..0");
+
+        // マス数が合わない。
+        assert!(Board::from_kinds(nz(2), nz(2), &[Some(0), Some(0)]).is_err());
+        // 牌種が範囲外。
+        assert!(Board::from_kinds(nz(2), nz(1), &[Some(TILE_KIND_COUNT); 2]).is_err());
+        // 枚数が奇数の牌種がある。
+        assert!(Board::from_kinds(nz(2), nz(2), &[Some(0), Some(1), None, Some(0)]).is_err());
+    }
+
+    #[test]
+    fn get_out_of_range() {
+        // 外周を含めて 4x3 の盤面。
+        let board = Board::from_string_repr("2x1:00", Rule::default()).unwrap();
+
+        assert_eq!(board.get(0, 0), Some(BoardCell::Empty));
+        assert_eq!(board.get(2, 1), Some(BoardCell::Tile(0)));
+        assert_eq!(board.get(3, 2), Some(BoardCell::Empty));
+        assert_eq!(board.get(4, 0), None);
+        assert_eq!(board.get(0, 3), None);
+        assert_eq!(board.get(usize::MAX, usize::MAX), None);
+    }
+
+    #[test]
+    fn cmp_preference_order() {
+        let mv = |path: &[(usize, usize)]| Move {
+            path: path.iter().map(|&(c, r)| Square::new(c, r)).collect(),
+        };
+
+        // 始点 (1,1), 終点 (3,3) の着手を優先される順に並べたもの。
+        let expected = [
+            // 経路長 4, 1 回曲がり, 縦始まり。
+            mv(&[(1, 1), (1, 3), (3, 3)]),
+            // 経路長 4, 1 回曲がり, 横始まり。
+            mv(&[(1, 1), (3, 1), (3, 3)]),
+            // 経路長 4, 2 回曲がり, 縦始まり。
+            mv(&[(1, 1), (1, 2), (3, 2), (3, 3)]),
+            // 経路長 4, 2 回曲がり, 横始まり。
+            mv(&[(1, 1), (2, 1), (2, 3), (3, 3)]),
+            // 経路長 6, 2 回曲がり, 縦始まり。曲がり角のマスで比べる。
+            mv(&[(1, 1), (1, 0), (3, 0), (3, 3)]),
+            mv(&[(1, 1), (1, 4), (3, 4), (3, 3)]),
+            // 経路長 6, 2 回曲がり, 横始まり。
+            mv(&[(1, 1), (0, 1), (0, 3), (3, 3)]),
+        ];
+
+        let mut moves = expected.to_vec();
+        moves.reverse();
+        moves.sort_by(Move::cmp_preference);
+        assert_eq!(moves, expected);
+
+        for (mv1, mv2) in expected.iter().tuple_combinations() {
+            assert_eq!(mv1.cmp_preference(mv2), std::cmp::Ordering::Less);
+            assert_eq!(mv2.cmp_preference(mv1), std::cmp::Ordering::Greater);
+        }
+    }
+
+    #[test]
+    fn moves_of_kind_pairs_of_one_kind() {
+        let board = Board::from_string_repr("4x1:0110", Rule::default()).unwrap();
+
+        let moves = board.moves_of_kind(1);
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].to_string_repr(), "2,1-3,1");
+
+        // 外周を回る最短経路の着手を返す。
+        let moves = board.moves_of_kind(0);
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].path().len(), 4);
+        assert!(board.is_legal(&moves[0]));
+
+        assert!(board.moves_of_kind(2).is_empty());
+
+        // 同じグループに属する他の種類の牌との着手は含まない。
+        let rule = Rule {
+            groups: vec![TileGroup::new([0, 1])],
+            ..Default::default()
+        };
+        let board = Board::from_string_repr("2x1:01", rule).unwrap();
+        assert_eq!(board.count_moves(), 1);
+        assert!(board.moves_of_kind(0).is_empty());
+        assert!(board.moves_of_kind(1).is_empty());
+    }
+
+    #[test]
+    fn random_and_shuffle_with_rng_are_deterministic() {
+        let rng = |seed| BoardRng::seed_from_u64(seed);
+        let random = |seed| Board::random_with_rng(nz(6), nz(9), &mut rng(seed)).to_string_repr();
+
+        assert_eq!(random(1), random(1));
+        assert_ne!(random(1), random(2));
+
+        let board = Board::random_with_rng(nz(6), nz(9), &mut rng(1));
+        let shuffle = |seed| {
+            let mut board = board.clone();
+            board.shuffle_with_rng(&mut rng(seed)).unwrap();
+            board.to_string_repr()
+        };
+
+        assert_eq!(shuffle(1), shuffle(1));
+        assert_ne!(shuffle(1), shuffle(2));
+    }
+
+    #[test]
+    fn are_connectable_cases() {
+        let board = Board::from_string_repr("4x1:0110", Rule::default()).unwrap();
+        let sq = |c| Square::new(c, 1);
+
+        assert!(board.are_connectable(sq(2), sq(3)));
+        assert!(board.are_connectable(sq(3), sq(2)));
+        // 同じマス。
+        assert!(!board.are_connectable(sq(2), sq(2)));
+        // 種類が異なる。
+        assert!(!board.are_connectable(sq(1), sq(2)));
+        // 空きマス。
+        assert!(!board.are_connectable(sq(0), sq(5)));
+
+        // 外周を使えなければ、間の牌に阻まれて取れない。
+        assert!(board.are_connectable(sq(1), sq(4)));
+        let rule = Rule {
+            allow_edge_routing: false,
+            ..Default::default()
+        };
+        let board = Board::from_string_repr("4x1:0110", rule).unwrap();
+        assert!(!board.are_connectable(sq(1), sq(4)));
+    }
+
+    #[test]
+    fn random_with_hole_parity() {
+        for gravity in [GravityMode::None, GravityMode::Down] {
+            let rule = Rule {
+                gravity,
+                ..Default::default()
+            };
+            let hole = Square::new(3, 4);
+            let board = Board::random_with_hole(nz(5), nz(7), rule, hole);
+
+            // 35 マスのうち穴を除く 34 マスに牌を置く。
+            assert_eq!(board[hole], BoardCell::Empty);
+            assert_eq!(board.tile_count(), 5 * 7 - 1);
+            assert!(board.kind_counts().iter().all(|&n| n % 2 == 0));
+            assert!(board.is_solvable());
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn random_with_hole_even_dimension() {
+        Board::random_with_hole(nz(6), nz(7), Rule::default(), Square::new(1, 1));
+    }
+
+    #[test]
+    fn odd_board_hole_is_passable() {
+        let rule = Rule {
+            allow_edge_routing: false,
+            ..Default::default()
+        };
+
+        // 両方の次元が奇数でも、牌の数が偶数なら復元できる。中央の穴を経路が通る。
+        let board = Board::from_string_repr("3x1:0.0", rule.clone()).unwrap();
+        assert!(board.are_connectable(Square::new(1, 1), Square::new(3, 1)));
+
+        // 牌の数が奇数ならエラー。
+        assert!(Board::from_string_repr("3x1:0..", rule).is_err());
+    }
+
+    #[test]
+    fn resolve_and_apply_moves() {
+        let rule = Rule {
+            allow_edge_routing: false,
+            ..Default::default()
+        };
+        let board = Board::from_string_repr("4x1:0110", rule).unwrap();
+        let sq = |c| Square::new(c, 1);
+
+        // 間の牌を取れば、両端の牌がまっすぐ取れる。
+        let moves = board
+            .resolve_moves(&[(sq(3), sq(2)), (sq(1), sq(4))])
+            .unwrap();
+        assert_eq!(moves[1].path(), [sq(1), sq(4)]);
+
+        let mut solved = board.clone();
+        solved.apply_moves(&moves).unwrap();
+        assert!(solved.is_empty());
+
+        // 不正な着手の位置がエラーに含まれ、盤面は変更されない。
+        let err = board
+            .resolve_moves(&[(sq(2), sq(3)), (sq(2), sq(3))])
+            .unwrap_err();
+        assert!(err.to_string().starts_with("move 1:"), "{err}");
+
+        let mut reversed = board.clone();
+        let err = reversed
+            .apply_moves(&[moves[1].clone(), moves[0].clone()])
+            .unwrap_err();
+        assert!(err.to_string().starts_with("move 0:"), "{err}");
+        assert_eq!(reversed.to_string_repr(), board.to_string_repr());
+    }
+
+    #[test]
+    fn layout_with_isolated_pockets_is_rejected() {
+        // 牌を置くマスが牌を置けないマスで互いに隔てられている。
+        let open = [Square::new(2, 2), Square::new(4, 2)];
+        let result =
+            Board::random_with_layout(nz(5), nz(3), Rule::default(), |sq| !open.contains(&sq));
+        assert!(result.is_err());
+
+        // 3 マスの区画で 1 組取ると、残りの 2 枚が別々の区画に取り残される。
+        let open = [2, 3, 4, 6].map(|c| Square::new(c, 2));
+        let result =
+            Board::random_with_layout(nz(7), nz(3), Rule::default(), |sq| !open.contains(&sq));
+        assert!(result.is_err());
+
+        // シャッフルも失敗し、盤面は変更しない。
+        let mut board = Board::from_string_repr("5x3:######0#0######", Rule::default()).unwrap();
+        let before = board.to_string_repr();
+        assert!(board.shuffle_solvable().is_err());
+        assert_eq!(board.to_string_repr(), before);
+    }
 }
@@ -0,0 +1,71 @@
+//! web 層を使わずに盤面の生成と着手を行い、処理時間を計測する。
+//!
+//! ネイティブ環境でのベンチマーク用。`sim` feature が有効な場合のみビルドされる。
+
+use std::num::NonZeroUsize;
+use std::time::Duration;
+
+use instant::Instant;
+
+use crate::shisen::Board;
+
+/// `simulate()` の結果。
+#[derive(Clone, Debug)]
+pub struct SimReport {
+    /// 盤面の生成にかかった時間。
+    pub generate: Duration,
+    /// `Board::shuffle_solvable()` にかかった時間。
+    pub shuffle: Duration,
+    /// `Board::find_move()` にかかった時間の合計。
+    pub find_move: Duration,
+    /// `Board::random_move()` にかかった時間の合計。
+    pub random_move: Duration,
+    /// 行った着手の数。
+    pub moves: usize,
+    /// 盤面を空にできたかどうか。false なら手詰まりで終わった。
+    pub cleared: bool,
+}
+
+/// シードを指定して盤面を生成し、シャッフルしてから、空になるか手詰まりになるまで
+/// ランダムな着手を続ける。各処理の時間を計測して返す。
+///
+/// シャッフルと着手の選択はシードによらずランダムに行う。
+pub fn simulate(ncol_inner: NonZeroUsize, nrow_inner: NonZeroUsize, seed: u64) -> SimReport {
+    let now = Instant::now();
+    let mut board = Board::random_seeded(ncol_inner, nrow_inner, seed);
+    let generate = now.elapsed();
+
+    let now = Instant::now();
+    board
+        .shuffle_solvable()
+        .expect("random board should be shuffled");
+    let shuffle = now.elapsed();
+
+    let mut find_move = Duration::ZERO;
+    let mut random_move = Duration::ZERO;
+    let mut moves = 0;
+    loop {
+        let now = Instant::now();
+        let found = board.find_move();
+        find_move += now.elapsed();
+        if found.is_none() {
+            break;
+        }
+
+        let now = Instant::now();
+        let mv = board.random_move().expect("legal move should exist");
+        random_move += now.elapsed();
+
+        board.do_move(&mv);
+        moves += 1;
+    }
+
+    SimReport {
+        generate,
+        shuffle,
+        find_move,
+        random_move,
+        moves,
+        cleared: board.is_empty(),
+    }
+}
@@ -1,9 +1,15 @@
 use std::ops::RangeInclusive;
 use std::time::Duration;
 
+use instant::Instant;
+use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use web_sys::{Document, Window};
+use web_sys::{CustomEvent, CustomEventInit, Document, Window};
 
+/// 2 つの閉区間の共通部分を返す。
+///
+/// 共通部分がない場合、`start > end` となる空の区間を返す (イテレートしても何も得られない)。
+/// 端点が一致するだけの区間同士では、その 1 点のみからなる区間を返す。
 pub fn range_intersection<T: Copy + Ord>(
     r1: RangeInclusive<T>,
     r2: RangeInclusive<T>,
@@ -14,13 +20,133 @@ pub fn range_intersection<T: Copy + Ord>(
     min..=max
 }
 
+/// 閉区間が空 (`start > end`) かどうかを返す。
+pub fn is_empty_range<T: Ord>(r: &RangeInclusive<T>) -> bool {
+    r.start() > r.end()
+}
+
+/// 経過時間を `MM:SS` 形式で返す。1 時間以上なら `HH:MM:SS` 形式で返す。
 pub fn format_duration(dur: Duration) -> String {
     let sec = dur.as_secs();
 
+    let hour = sec / 3600;
+    let min = sec / 60 % 60;
+    let sec = sec % 60;
+
+    if hour > 0 {
+        format!("{hour:02}:{min:02}:{sec:02}")
+    } else {
+        format!("{min:02}:{sec:02}")
+    }
+}
+
+/// 経過時間を 1/100 秒まで `MM:SS.cc` 形式で返す。
+///
+/// タイムを比べやすいよう、1 時間以上でも時間の桁を設けず分を 60 以上に繰り上げる。
+pub fn format_duration_precise(dur: Duration) -> String {
+    let sec = dur.as_secs();
+
     let min = sec / 60;
     let sec = sec % 60;
+    let centi = dur.subsec_millis() / 10;
+
+    format!("{min:02}:{sec:02}.{centi:02}")
+}
+
+/// 一時停止可能なストップウォッチ。
+#[derive(Debug)]
+pub struct Stopwatch {
+    /// 前回の一時停止までに経過した時間。
+    accum: Duration,
+    /// 計測中なら、計測を(再)開始した時刻。
+    since: Option<Instant>,
+}
+
+impl Stopwatch {
+    /// 計測中のストップウォッチを返す。
+    pub fn start() -> Self {
+        Self {
+            accum: Duration::ZERO,
+            since: Some(Instant::now()),
+        }
+    }
+
+    /// 経過時間 0 で一時停止中のストップウォッチを返す。
+    pub fn paused() -> Self {
+        Self {
+            accum: Duration::ZERO,
+            since: None,
+        }
+    }
+
+    /// 既に `elapsed` だけ経過した状態から計測を開始したストップウォッチを返す。
+    pub fn start_with_elapsed(elapsed: Duration) -> Self {
+        Self {
+            accum: elapsed,
+            since: Some(Instant::now()),
+        }
+    }
+
+    /// 経過時間を返す。一時停止中の時間は含まない。
+    pub fn elapsed(&self) -> Duration {
+        self.accum + self.since.map_or(Duration::ZERO, |since| since.elapsed())
+    }
+
+    /// 一時停止中かどうかを返す。
+    pub fn is_paused(&self) -> bool {
+        self.since.is_none()
+    }
+
+    /// 一時停止する。既に一時停止中なら何もしない。
+    pub fn pause(&mut self) {
+        if let Some(since) = self.since.take() {
+            self.accum += since.elapsed();
+        }
+    }
+
+    /// 計測を再開する。既に計測中なら何もしない。
+    pub fn resume(&mut self) {
+        if self.since.is_none() {
+            self.since = Some(Instant::now());
+        }
+    }
+}
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = ["navigator", "clipboard"], js_name = writeText)]
+    fn clipboard_write_text(text: &str) -> JsValue;
+}
+
+/// テキストをクリップボードにコピーする。完了は待たない。
+pub fn copy_to_clipboard(text: &str) {
+    let _ = clipboard_write_text(text);
+}
 
-    format!("{min:02}:{sec:02}")
+/// OS の設定でアニメーションの抑制 (`prefers-reduced-motion: reduce`) が求められているかを返す。
+pub fn prefers_reduced_motion() -> bool {
+    window()
+        .match_media("(prefers-reduced-motion: reduce)")
+        .ok()
+        .flatten()
+        .is_some_and(|mql| mql.matches())
+}
+
+/// ブラウザの UI の言語 (`navigator.language`) を返す。取得できなければ None を返す。
+pub fn browser_language() -> Option<String> {
+    window().navigator().language()
+}
+
+/// `window` に名前 `name` の `CustomEvent` を発行する。`detail` はイベントの `detail` になる。
+///
+/// 埋め込み先のページへの通知用。発行に失敗しても無視する。
+pub fn dispatch_custom_event(name: &str, detail: &JsValue) {
+    let mut init = CustomEventInit::new();
+    init.detail(detail);
+
+    if let Ok(event) = CustomEvent::new_with_event_init_dict(name, &init) {
+        let _ = window().dispatch_event(&event);
+    }
 }
 
 pub fn window() -> Window {
@@ -31,6 +157,22 @@ pub fn document() -> Document {
     window().document().expect("document should exist")
 }
 
+/// 指定した ID の要素を返す。要素が存在しない場合は None を返す。
+///
+/// 要素を `T` にキャストできない場合、panic する。
+pub fn try_get_element_by_id<T: JsCast>(id: impl AsRef<str>) -> Option<T> {
+    let id = id.as_ref();
+
+    document().get_element_by_id(id).map(|elem| {
+        elem.dyn_into().unwrap_or_else(|_| {
+            panic!(
+                "element '{id}': cannot cast into `{}`",
+                std::any::type_name::<T>()
+            )
+        })
+    })
+}
+
 pub fn get_element_by_id<T: JsCast>(id: impl AsRef<str>) -> T {
     _get_element_by_id(id.as_ref())
 }
@@ -47,3 +189,68 @@ fn _get_element_by_id<T: JsCast>(id: &str) -> T {
             )
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_duration_hours() {
+        assert_eq!(format_duration(Duration::from_secs(5)), "00:05");
+        assert_eq!(format_duration(Duration::from_secs(59 * 60 + 59)), "59:59");
+        assert_eq!(format_duration(Duration::from_secs(3600)), "01:00:00");
+        assert_eq!(
+            format_duration(Duration::from_secs(12 * 3600 + 34 * 60 + 56)),
+            "12:34:56"
+        );
+        assert_eq!(
+            format_duration(Duration::from_secs(100 * 3600)),
+            "100:00:00"
+        );
+    }
+
+    #[test]
+    fn format_duration_precise_rolls_minutes() {
+        assert_eq!(
+            format_duration_precise(Duration::from_millis(3_600_123)),
+            "60:00.12"
+        );
+        assert_eq!(
+            format_duration_precise(Duration::from_secs(75 * 60)),
+            "75:00.00"
+        );
+    }
+
+    #[test]
+    fn format_duration_precise_minute_boundary() {
+        assert_eq!(
+            format_duration_precise(Duration::from_millis(59_999)),
+            "00:59.99"
+        );
+        assert_eq!(format_duration_precise(Duration::from_secs(60)), "01:00.00");
+    }
+
+    #[test]
+    fn range_intersection_edge_cases() {
+        // 離れた区間。
+        let r = range_intersection(0..=2, 5..=7);
+        assert!(is_empty_range(&r));
+        assert_eq!(r.count(), 0);
+
+        // 隣り合うが重ならない区間。
+        assert!(is_empty_range(&range_intersection(0..=2, 3..=7)));
+
+        // 端点で接する区間は、その 1 点になる。
+        let r = range_intersection(0..=3, 3..=7);
+        assert_eq!(r, 3..=3);
+        assert!(!is_empty_range(&r));
+
+        // 1 点のみの区間。
+        assert_eq!(range_intersection(4..=4, 0..=10), 4..=4);
+        assert!(is_empty_range(&range_intersection(4..=4, 5..=10)));
+
+        // 包含。
+        assert_eq!(range_intersection(0..=10, 3..=7), 3..=7);
+        assert_eq!(range_intersection(3..=7, 0..=10), 3..=7);
+    }
+}
@@ -2,43 +2,260 @@
 // DOM 構造が変わらない場合に el_ref() が効かない問題を避けるため。
 // (seed の差分更新の問題?同じ DOM 要素に対して異なる ElRef インスタンスを生成すると壊れるのかも)
 
+use std::collections::VecDeque;
 use std::num::NonZeroUsize;
 use std::time::Duration;
 
+use anyhow::{anyhow, Context as _};
 use instant::Instant;
+use itertools::Itertools as _;
 use seed::{prelude::*, *};
-use web_sys::{HtmlCanvasElement, MouseEvent};
-
-use crate::asset::Asset;
-use crate::shisen::{Board, BoardCell, Move, Square};
-use crate::util;
-
+use web_sys::{
+    CanvasRenderingContext2d, HtmlCanvasElement, ImageBitmap, KeyboardEvent, MouseEvent, Touch,
+    TouchEvent,
+};
+
+use crate::asset::{self, Asset};
+use crate::i18n::{Lang, Text};
+use crate::save::{SavedGame, StoredSettings};
+use crate::score::{PlayStats, Score};
+use crate::shisen::{Board, BoardCell, GravityMode, Move, Rule, Square, Symmetry, TILE_KIND_COUNT};
+use crate::util::{self, Stopwatch};
+
+/// 盤面の大きさ(外周を除く)のデフォルト値。
 const NCOL_INNER: usize = 6;
 const NROW_INNER: usize = 9;
 
+/// デフォルトの大きさの盤面でのキャンバスの幅。マスの幅はこれから決める。
+/// キャンバスの大きさは盤面の大きさに合わせる。
 const CANVAS_WIDTH: u32 = 468;
 
 const TILE_WIDTH: u32 = CANVAS_WIDTH / (NCOL_INNER + 2) as u32;
-const TILE_HEIGHT: u32 = TILE_WIDTH;
 
-const CANVAS_HEIGHT: u32 = TILE_HEIGHT * (NROW_INNER + 2) as u32;
+/// マスの縦横比。マスの幅は常に `TILE_WIDTH` で、高さをこれで決める。
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+enum TileAspect {
+    #[default]
+    Square,
+    /// 麻雀牌のような縦長 (幅:高さ = 3:4)。
+    Tall,
+}
+
+impl TileAspect {
+    fn tile_size(self) -> TileSize {
+        let height = match self {
+            Self::Square => TILE_WIDTH,
+            Self::Tall => TILE_WIDTH * 4 / 3,
+        };
+
+        TileSize {
+            width: TILE_WIDTH,
+            height,
+        }
+    }
+}
+
+/// マスの外周に設ける余白(px)。牌はマスからこの分だけ内側に描画する。
+const TILE_MARGIN: u32 = 1;
+
+/// 牌を角丸で描画する場合の角の半径(px)。
+const TILE_RADIUS: f64 = 5.0;
+
+/// クリア画面に表示する開始時の盤面のサムネイルの縮小率。マスの大きさをこの値で割る。
+const THUMBNAIL_SCALE: u32 = 4;
+
+/// タイマーのティック間隔(ミリ秒)。
+const TICK_MS: u32 = 16;
+
+/// リプレイ再生時の着手間隔(ティック数)。
+const REPLAY_INTERVAL: u32 = 45;
+
+/// ゲーム開始前のカウントダウンのティック数。"3", "2", "1" を 1 秒ずつ、最後に "Go!" を表示する。
+const COUNTDOWN_TICKS: u32 = (3000 + COUNTDOWN_GO_MS) / TICK_MS;
+
+/// カウントダウンの最後に "Go!" を表示する時間(ミリ秒)。
+const COUNTDOWN_GO_MS: u32 = 500;
+
+/// 最終手の経路の表示時間(ミリ秒)のデフォルト値と最大値。
+const PATH_DURATION_MS_DEFAULT: u32 = 500;
+const PATH_DURATION_MS_MAX: u32 = 2000;
+
+/// 最終手の経路の表示時間のうち、末尾でフェードアウトする割合。
+const PATH_FADE_FRACTION: f64 = 0.3;
+
+/// 1 ゲームで使えるヒントの数。
+const HINT_COUNT: u32 = 3;
+
+/// ヒント 1 回ごとにクリアタイムに加算するペナルティ。
+const HINT_PENALTY: Duration = Duration::from_secs(30);
+
+/// 取れない牌の組を選んだときに赤く点滅させる時間。
+const ERROR_FLASH_DURATION: Duration = Duration::from_millis(320);
+
+/// 同じ種類の牌を続けて取った場合にコンボとみなす、前の着手からの時間。
+const COMBO_WINDOW: Duration = Duration::from_secs(3);
+
+/// 同じマスを続けてクリックした場合にダブルクリックとみなす、前のクリックからの時間。
+const DOUBLE_CLICK_WINDOW: Duration = Duration::from_millis(300);
+
+/// ゲームが終わったときに `window` に発行する `CustomEvent` の名前。
+/// `detail` の形式は `dispatch_game_end()` を参照。
+const EVENT_WIN: &str = "shisen:win";
+const EVENT_STUCK: &str = "shisen:stuck";
+const EVENT_TIME_UP: &str = "shisen:timeup";
+
+/// 選択可能なラッシュモードの制限時間(秒)と、その表示名。0 は制限なし。
+const RUSH_LIMITS: [(u64, Text); 4] = [
+    (0, Text::NoTimeLimit),
+    (60, Text::Rush1Min),
+    (180, Text::Rush3Min),
+    (300, Text::Rush5Min),
+];
+
+/// 選択可能な牌を寄せる方向と、その UI 上の値/表示名。
+const GRAVITY_MODES: [(GravityMode, &str, Text); 5] = [
+    (GravityMode::None, "none", Text::NoGravity),
+    (GravityMode::Down, "down", Text::GravityDown),
+    (GravityMode::Up, "up", Text::GravityUp),
+    (GravityMode::Left, "left", Text::GravityLeft),
+    (GravityMode::Right, "right", Text::GravityRight),
+];
+
+/// 選択可能な盤面の対称性と、その UI 上の値/表示名。None は対称にしない。
+const SYMMETRIES: [(Option<Symmetry>, &str, Text); 3] = [
+    (None, "none", Text::NoSymmetry),
+    (Some(Symmetry::Mirror), "mirror", Text::SymmetryMirror),
+    (Some(Symmetry::Point), "point", Text::SymmetryPoint),
+];
+
+/// 選択可能なマスの縦横比と、その UI 上の値/表示名。
+const TILE_ASPECTS: [(TileAspect, &str, Text); 2] = [
+    (TileAspect::Square, "square", Text::SquareTiles),
+    (TileAspect::Tall, "tall", Text::TallTiles),
+];
+
+/// 難易度。盤面の大きさ、牌種の数、初期状態の合法手の最小数をまとめて決める。
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+/// 選択可能な UI の言語と、その UI 上の値/表示名。表示名はその言語自身で書く。
+const LANGS: [(Lang, &str, &str); 2] = [(Lang::En, "en", "English"), (Lang::Ja, "ja", "日本語")];
+
+/// 選択可能な難易度と、その UI 上の値/表示名。
+const DIFFICULTIES: [(Difficulty, &str, Text); 3] = [
+    (Difficulty::Easy, "easy", Text::Easy),
+    (Difficulty::Normal, "normal", Text::Normal),
+    (Difficulty::Hard, "hard", Text::Hard),
+];
+
+impl Difficulty {
+    /// 難易度に対応する (外周を除く列数, 行数, 牌種の数, 初期状態の合法手の最小数) を返す。
+    ///
+    /// 易しいほど牌種を減らして同じ牌を増やし、最初に取れる組を多く保証する。
+    fn params(self) -> (usize, usize, usize, usize) {
+        match self {
+            Self::Easy => (6, 7, 8, 6),
+            Self::Normal => (NCOL_INNER, NROW_INNER, 20, 3),
+            Self::Hard => (NCOL_INNER, NROW_INNER, TILE_KIND_COUNT, 1),
+        }
+    }
+}
 
 #[wasm_bindgen(start)]
 pub fn start() {
     App::start("app", init, update, view);
 }
 
-fn init(_: Url, orders: &mut impl Orders<Msg>) -> Model {
-    orders
-        .perform_cmd(async {
-            Asset::load()
-                .await
-                .map(Msg::AssetLoad)
-                .expect("cannot load asset")
-        })
-        .stream(streams::interval(16, || Msg::Timer));
+fn init(url: Url, orders: &mut impl Orders<Msg>) -> Model {
+    // 共有された盤面がクエリにあればそれを使う。不正なら無視してランダムな盤面にする。
+    let query = |key: &str| url.search().get(key).and_then(|values| values.first());
+
+    let mut settings = Settings {
+        reduced_motion_os: util::prefers_reduced_motion(),
+        lang_browser: util::browser_language().map_or(Lang::En, |tag| Lang::from_tag(&tag)),
+        ..Default::default()
+    };
+    // 保存された設定を適用し、クエリでの指定があればそちらを優先する。
+    settings.apply_stored(&StoredSettings::load());
+    // 牌種の数は難易度より個別の指定を優先する。
+    if let Some(difficulty) = query("difficulty").and_then(|value| difficulty_from_value(value)) {
+        settings.set_difficulty(difficulty);
+    }
+    if let Some(gravity) = query("gravity").and_then(|value| gravity_from_value(value)) {
+        settings.gravity = gravity;
+    }
+    if let Some(tile_kinds) = query("kinds")
+        .and_then(|value| value.parse().ok())
+        .filter(|tile_kinds| (1..=TILE_KIND_COUNT).contains(tile_kinds))
+    {
+        settings.tile_kinds = tile_kinds;
+    }
+
+    load_asset(orders, &settings);
+    orders.stream(streams::interval(TICK_MS, || Msg::Timer));
+    orders.stream(streams::window_event(Ev::KeyDown, |ev| {
+        key_shortcut(ev.unchecked_ref())
+    }));
+    orders.stream(streams::window_event(Ev::Blur, |_| Msg::WindowBlur));
+    orders.stream(streams::window_event(Ev::Focus, |_| Msg::WindowFocus));
+
+    let board = query("board").and_then(|repr| {
+        let rule = settings.rule();
+        Board::from_string_repr(repr, rule).ok()
+    });
+
+    // リプレイは共有された盤面に対してのみ有効。
+    let replay = board
+        .as_ref()
+        .and(query("replay"))
+        .and_then(|replay| parse_replay(replay).ok());
+
+    Model::new(settings, board, replay)
+}
+
+/// 設定に従ってアセットの読み込みを開始する。
+///
+/// 読み込み中は `Msg::AssetProgress` が、完了時に `Msg::AssetLoad` または `Msg::AssetLoadError` が送られる。
+fn load_asset(orders: &mut impl Orders<Msg>, settings: &Settings) {
+    let theme = settings.theme.clone();
+    let tile_kinds = settings.tile_kinds;
+    let sender = orders.msg_sender();
+    orders.perform_cmd(async move {
+        let on_progress = move |loaded, total| sender(Some(Msg::AssetProgress(loaded, total)));
+        match Asset::load(&theme, tile_kinds, on_progress).await {
+            Ok(asset) => Msg::AssetLoad(asset),
+            Err(e) => Msg::AssetLoadError(format!("{e:#}")),
+        }
+    });
+}
+
+/// キーボードショートカットに対応するメッセージを返す。
+///
+/// R: やり直し、H: ヒント、U: 着手の取り消し。修飾キー付きの場合や、入力欄にフォーカスがある場合は無視する。
+/// メッセージを受け付けない状態では、各状態の `update()` で無視される。
+fn key_shortcut(ev: &KeyboardEvent) -> Option<Msg> {
+    if ev.ctrl_key() || ev.meta_key() || ev.alt_key() || ev.repeat() {
+        return None;
+    }
 
-    Model::new()
+    let in_input = ev
+        .target()
+        .and_then(|target| target.dyn_into::<web_sys::Element>().ok())
+        .is_some_and(|elem| matches!(elem.tag_name().as_str(), "INPUT" | "TEXTAREA" | "SELECT"));
+    if in_input {
+        return None;
+    }
+
+    match ev.key().as_str() {
+        "r" | "R" => Some(Msg::Restart),
+        "h" | "H" => Some(Msg::Hint),
+        "u" | "U" => Some(Msg::Undo),
+        _ => None,
+    }
 }
 
 fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>) {
@@ -53,29 +270,82 @@ fn view(model: &Model) -> Node<Msg> {
 #[derive(Debug)]
 enum Msg {
     AssetLoad(Asset),
+    AssetLoadError(String),
+    AssetProgress(usize, usize),
+    Retry,
     Restart,
+    ReplayBoard,
+    Hint,
+    SmartHint,
+    Undo,
+    CheckSolvable,
+    CheckDeadEnd,
+    Shuffle,
+    AutoSolve,
+    Pause,
+    Resume,
+    WindowBlur,
+    WindowFocus,
+    CopyReplay,
+    ResumeSaved,
     ModelInit,
     Timer,
     DrawCanvas,
     CanvasClick(MouseEvent),
+    CanvasRightClick,
+    CanvasMouseMove(MouseEvent),
+    CanvasMouseLeave,
+    CanvasTouchStart(TouchEvent),
+    CanvasTouchMove(TouchEvent),
+    CanvasTouchEnd(TouchEvent),
+    GravityChange(String),
+    SymmetryChange(String),
+    DifficultyChange(String),
+    RushChange(String),
+    ThemeChange(String),
+    PathDurationChange(String),
+    SettingsToggle,
+    ReducedMotionChange(String),
+    LangChange(String),
+    TileAspectChange(String),
+    ShowKindsToggle,
+    KindLegendToggle,
+    HistoryFlash(usize),
+    DebugGridToggle,
+    EndlessToggle,
+    RoundedTilesToggle,
+    WarnDeadEndToggle,
+    BorderToggle,
+    ContinuousMatchToggle,
+    CountdownToggle,
+    ClockOnFirstMoveToggle,
+    ShowTargetsToggle,
+    MuteToggle,
+    VolumeChange(String),
 }
 
 #[derive(Debug)]
 enum Model {
     Loading(ModelLoading),
-    Playing(ModelPlaying),
+    Error(ModelError),
+    Playing(Box<ModelPlaying>),
     Win(ModelWin),
     Stuck(ModelStuck),
 }
 
 impl Model {
-    fn new() -> Self {
-        Self::default()
+    fn new(
+        settings: Settings,
+        board: Option<Board>,
+        replay: Option<Vec<(Square, Square)>>,
+    ) -> Self {
+        Self::Loading(ModelLoading::new(settings, board, replay))
     }
 
     fn update(self, msg: Msg, orders: &mut impl Orders<Msg>) -> Model {
         match self {
             Model::Loading(inner) => inner.update(msg, orders),
+            Model::Error(inner) => inner.update(msg, orders),
             Model::Playing(inner) => inner.update(msg, orders),
             Model::Win(inner) => inner.update(msg, orders),
             Model::Stuck(inner) => inner.update(msg, orders),
@@ -85,6 +355,7 @@ impl Model {
     fn view(&self) -> Node<Msg> {
         match self {
             Model::Loading(inner) => inner.view(),
+            Model::Error(inner) => inner.view(),
             Model::Playing(inner) => inner.view(),
             Model::Win(inner) => inner.view(),
             Model::Stuck(inner) => inner.view(),
@@ -98,246 +369,2693 @@ impl Default for Model {
     }
 }
 
+/// ゲームの設定。各状態間で引き継がれる。
+#[derive(Clone, Debug)]
+struct Settings {
+    gravity: GravityMode,
+    /// 新しい盤面の対称性。None なら対称にしない。
+    symmetry: Option<Symmetry>,
+    /// 牌画像のテーマ名。
+    theme: String,
+    /// 最終手の経路の表示時間(ミリ秒)。
+    path_duration_ms: u32,
+    /// 使う牌種の数。
+    tile_kinds: usize,
+    /// 新しい盤面の大きさ(外周を除く)。
+    ncol_inner: usize,
+    nrow_inner: usize,
+    /// 新しい盤面で保証する、初期状態の合法手の最小数。
+    min_opening_moves: usize,
+    /// エンドレスモード。盤面を空にすると新しい盤面で続ける。
+    endless: bool,
+    /// 牌を角丸にし、影を付けて描画するかどうか。描画が重い環境では無効にする。
+    rounded_tiles: bool,
+    /// ラッシュモードの制限時間。None なら制限なし。
+    rush_limit: Option<Duration>,
+    /// OS の設定でアニメーションの抑制が求められているかどうか。起動時に決める。
+    reduced_motion_os: bool,
+    /// アニメーションを抑制するかどうかのユーザーによる指定。None なら OS の設定に従う。
+    reduced_motion_override: Option<bool>,
+    /// 新しいゲームで牌の種類の番号を表示するかどうか。
+    show_kinds: bool,
+    /// 着手のたびに盤面を空にできるか調べ、できなくなったら警告するかどうか。
+    /// 調べるのが重いのでデフォルトでは無効。
+    warn_dead_end: bool,
+    /// 経路が通れる盤面の外周を、背景と区別できる色で描画するかどうか。
+    show_border: bool,
+    /// マスの縦横比。
+    tile_aspect: TileAspect,
+    /// 着手後、取った牌と同じ種類の牌が残っていればそれを選択したままにするかどうか。
+    /// 同じ種類の牌を続けて取りやすくなる。
+    continuous_match: bool,
+    /// 選択中の牌から取れる同じ種類の牌を光らせるかどうか。
+    show_targets: bool,
+    /// 新しいゲームの開始前にカウントダウンし、その間は操作を受け付けないかどうか。
+    countdown: bool,
+    /// 新しいゲームで、最初に牌を選択するか取るまで経過時間の計測を始めないかどうか。
+    /// カウントダウンと併用した場合、カウントダウンの後に最初の操作を待つ。
+    clock_on_first_move: bool,
+    /// ブラウザの UI の言語。起動時に決める。
+    lang_browser: Lang,
+    /// UI の言語のユーザーによる指定。None ならブラウザの設定に従う。
+    lang_override: Option<Lang>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            gravity: GravityMode::default(),
+            symmetry: None,
+            theme: asset::DEFAULT_THEME.to_owned(),
+            path_duration_ms: PATH_DURATION_MS_DEFAULT,
+            tile_kinds: TILE_KIND_COUNT,
+            ncol_inner: NCOL_INNER,
+            nrow_inner: NROW_INNER,
+            min_opening_moves: 1,
+            endless: false,
+            rounded_tiles: true,
+            rush_limit: None,
+            reduced_motion_os: false,
+            reduced_motion_override: None,
+            show_kinds: false,
+            warn_dead_end: false,
+            show_border: false,
+            tile_aspect: TileAspect::default(),
+            continuous_match: false,
+            show_targets: true,
+            countdown: false,
+            clock_on_first_move: false,
+            lang_browser: Lang::default(),
+            lang_override: None,
+        }
+    }
+}
+
+impl Settings {
+    /// 設定に対応する盤面のルールを返す。
+    fn rule(&self) -> Rule {
+        Rule {
+            gravity: self.gravity,
+            tile_kinds: self.tile_kinds,
+            ..Default::default()
+        }
+    }
+
+    /// アニメーションを抑制するかどうかを返す。
+    fn reduced_motion(&self) -> bool {
+        self.reduced_motion_override
+            .unwrap_or(self.reduced_motion_os)
+    }
+
+    /// UI の言語を返す。
+    fn lang(&self) -> Lang {
+        self.lang_override.unwrap_or(self.lang_browser)
+    }
+
+    /// 保存された設定を適用する。不正な値は無視する。
+    fn apply_stored(&mut self, stored: &StoredSettings) {
+        if let Some(difficulty) = stored.difficulty.as_deref().and_then(difficulty_from_value) {
+            self.set_difficulty(difficulty);
+        }
+        if let Some(gravity) = stored.gravity.as_deref().and_then(gravity_from_value) {
+            self.gravity = gravity;
+        }
+        if let Some(symmetry) = stored.symmetry.as_deref().and_then(symmetry_from_value) {
+            self.symmetry = symmetry;
+        }
+        if let Some(theme) = stored
+            .theme
+            .as_deref()
+            .filter(|&theme| asset::THEMES.iter().any(|e| e.0 == theme))
+        {
+            self.theme = theme.to_owned();
+        }
+        if let Some(ms) = stored.path_duration_ms {
+            self.path_duration_ms = ms.min(PATH_DURATION_MS_MAX);
+        }
+        if let Some(rounded_tiles) = stored.rounded_tiles {
+            self.rounded_tiles = rounded_tiles;
+        }
+        self.reduced_motion_override = stored.reduced_motion;
+        if let Some(show_kinds) = stored.show_kinds {
+            self.show_kinds = show_kinds;
+        }
+        if let Some(warn_dead_end) = stored.warn_dead_end {
+            self.warn_dead_end = warn_dead_end;
+        }
+        if let Some(show_border) = stored.show_border {
+            self.show_border = show_border;
+        }
+        if let Some(tile_aspect) = stored
+            .tile_aspect
+            .as_deref()
+            .and_then(tile_aspect_from_value)
+        {
+            self.tile_aspect = tile_aspect;
+        }
+        if let Some(continuous_match) = stored.continuous_match {
+            self.continuous_match = continuous_match;
+        }
+        if let Some(show_targets) = stored.show_targets {
+            self.show_targets = show_targets;
+        }
+        if let Some(countdown) = stored.countdown {
+            self.countdown = countdown;
+        }
+        if let Some(clock_on_first_move) = stored.clock_on_first_move {
+            self.clock_on_first_move = clock_on_first_move;
+        }
+        self.lang_override = stored.lang.as_deref().and_then(lang_from_value);
+    }
+
+    /// 設定を localStorage に保存する。
+    fn store(&self) {
+        StoredSettings {
+            difficulty: self.difficulty().map(|d| difficulty_value(d).to_owned()),
+            gravity: Some(gravity_value(self.gravity).to_owned()),
+            symmetry: Some(symmetry_value(self.symmetry).to_owned()),
+            theme: Some(self.theme.clone()),
+            path_duration_ms: Some(self.path_duration_ms),
+            rounded_tiles: Some(self.rounded_tiles),
+            reduced_motion: self.reduced_motion_override,
+            show_kinds: Some(self.show_kinds),
+            warn_dead_end: Some(self.warn_dead_end),
+            show_border: Some(self.show_border),
+            tile_aspect: Some(tile_aspect_value(self.tile_aspect).to_owned()),
+            continuous_match: Some(self.continuous_match),
+            lang: self.lang_override.map(|lang| lang_value(lang).to_owned()),
+            show_targets: Some(self.show_targets),
+            countdown: Some(self.countdown),
+            clock_on_first_move: Some(self.clock_on_first_move),
+        }
+        .store();
+    }
+
+    /// マスの大きさを返す。
+    fn tile_size(&self) -> TileSize {
+        self.tile_aspect.tile_size()
+    }
+
+    /// 最終手の経路の表示時間を返す。
+    fn path_duration(&self) -> Duration {
+        Duration::from_millis(self.path_duration_ms.into())
+    }
+
+    /// 設定に従ってランダムな盤面を生成する。
+    ///
+    /// 対称な盤面では、初期状態の合法手の最小数は保証しない (並べ直すと対称性が崩れるため)。
+    /// 両方の次元が奇数なら、中央のマスを穴にし、対称性と初期状態の合法手の最小数は無視する。
+    fn new_board(&self) -> Board {
+        let ncol_inner = NonZeroUsize::new(self.ncol_inner).unwrap();
+        let nrow_inner = NonZeroUsize::new(self.nrow_inner).unwrap();
+
+        if self.ncol_inner % 2 == 1 && self.nrow_inner % 2 == 1 {
+            let center = Square::new(self.ncol_inner / 2 + 1, self.nrow_inner / 2 + 1);
+            return Board::random_with_hole(ncol_inner, nrow_inner, self.rule(), center);
+        }
+
+        match self.symmetry {
+            Some(symmetry) => {
+                Board::random_symmetric_with_rule(ncol_inner, nrow_inner, self.rule(), symmetry)
+            }
+            None => Board::random_with_rule_and_opening(
+                ncol_inner,
+                nrow_inner,
+                self.rule(),
+                self.min_opening_moves,
+            ),
+        }
+    }
+
+    /// 難易度に対応する盤面の大きさ、牌種の数、初期状態の合法手の最小数を設定する。
+    fn set_difficulty(&mut self, difficulty: Difficulty) {
+        (
+            self.ncol_inner,
+            self.nrow_inner,
+            self.tile_kinds,
+            self.min_opening_moves,
+        ) = difficulty.params();
+    }
+
+    /// 現在の設定に一致する難易度を返す。どれにも一致しなければ None を返す。
+    fn difficulty(&self) -> Option<Difficulty> {
+        let params = (
+            self.ncol_inner,
+            self.nrow_inner,
+            self.tile_kinds,
+            self.min_opening_moves,
+        );
+
+        DIFFICULTIES
+            .iter()
+            .map(|e| e.0)
+            .find(|difficulty| difficulty.params() == params)
+    }
+}
+
+fn gravity_from_value(value: &str) -> Option<GravityMode> {
+    GRAVITY_MODES
+        .iter()
+        .find(|e| e.1 == value)
+        .map(|&(gravity, _, _)| gravity)
+}
+
+fn gravity_value(gravity: GravityMode) -> &'static str {
+    GRAVITY_MODES
+        .iter()
+        .find(|e| e.0 == gravity)
+        .map(|&(_, value, _)| value)
+        .expect("gravity mode should be listed")
+}
+
+/// UI 上の値に対応する対称性を返す。値が不正なら None を、対称にしない場合は Some(None) を返す。
+fn symmetry_from_value(value: &str) -> Option<Option<Symmetry>> {
+    SYMMETRIES
+        .iter()
+        .find(|e| e.1 == value)
+        .map(|&(symmetry, _, _)| symmetry)
+}
+
+fn symmetry_value(symmetry: Option<Symmetry>) -> &'static str {
+    SYMMETRIES
+        .iter()
+        .find(|e| e.0 == symmetry)
+        .map(|&(_, value, _)| value)
+        .expect("symmetry should be listed")
+}
+
+fn tile_aspect_from_value(value: &str) -> Option<TileAspect> {
+    TILE_ASPECTS
+        .iter()
+        .find(|e| e.1 == value)
+        .map(|&(tile_aspect, _, _)| tile_aspect)
+}
+
+fn tile_aspect_value(tile_aspect: TileAspect) -> &'static str {
+    TILE_ASPECTS
+        .iter()
+        .find(|e| e.0 == tile_aspect)
+        .map(|&(_, value, _)| value)
+        .expect("tile aspect should be listed")
+}
+
+fn lang_from_value(value: &str) -> Option<Lang> {
+    LANGS
+        .iter()
+        .find(|e| e.1 == value)
+        .map(|&(lang, _, _)| lang)
+}
+
+fn lang_value(lang: Lang) -> &'static str {
+    LANGS
+        .iter()
+        .find(|e| e.0 == lang)
+        .map(|&(_, value, _)| value)
+        .expect("language should be listed")
+}
+
+fn difficulty_from_value(value: &str) -> Option<Difficulty> {
+    DIFFICULTIES
+        .iter()
+        .find(|e| e.1 == value)
+        .map(|&(difficulty, _, _)| difficulty)
+}
+
+fn difficulty_value(difficulty: Difficulty) -> &'static str {
+    DIFFICULTIES
+        .iter()
+        .find(|e| e.0 == difficulty)
+        .map(|&(_, value, _)| value)
+        .expect("difficulty should be listed")
+}
+
+/// 現在の盤面を共有できるよう、URL のクエリを置き換える。
+fn replace_url_query(board: &Board, settings: &Settings) {
+    let mut params = vec![
+        ("board", vec![board.to_string_repr()]),
+        ("gravity", vec![gravity_value(settings.gravity).to_owned()]),
+        ("kinds", vec![settings.tile_kinds.to_string()]),
+    ];
+    if let Some(difficulty) = settings.difficulty() {
+        params.push(("difficulty", vec![difficulty_value(difficulty).to_owned()]));
+    }
+    let search = UrlSearch::new(params);
+
+    Url::current().set_search(search).go_and_replace();
+}
+
+/// リプレイ(着手の文字列表現を空白区切りで並べたもの)をパースし、各着手の (始点, 終点) を返す。
+fn parse_replay(s: &str) -> anyhow::Result<Vec<(Square, Square)>> {
+    s.split_whitespace().map(Move::parse_endpoints).collect()
+}
+
+/// マスをクリックしたときの動作。
+#[derive(Debug, Eq, PartialEq)]
+enum ClickAction {
+    /// 牌を選択する。
+    Select(Square),
+    /// 選択中の牌とクリックした牌を取る。
+    Play(Move),
+    /// 取れる牌同士だが経路がない。選択を解除し、両方の牌を赤く点滅させる。
+    Blocked(Square, Square),
+    /// 選択を解除する。
+    Deselect,
+    /// 何もしない。
+    Ignore,
+}
+
+/// 選択中のマスが `sq_select` の状態で、マス `sq` (盤面外なら None) をクリックしたときの動作を返す。
+///
+/// 牌のないマスや盤面外をクリックした場合は経路を探さず、選択中なら選択を解除し、
+/// 選択中でなければ何もしない。
+fn click_action(board: &Board, sq_select: Option<Square>, sq: Option<Square>) -> ClickAction {
+    let Some(sq) = sq.filter(|&sq| board[sq].is_tile()) else {
+        return match sq_select {
+            Some(_) => ClickAction::Deselect,
+            None => ClickAction::Ignore,
+        };
+    };
+    let Some(sq_select) = sq_select else {
+        return ClickAction::Select(sq);
+    };
+
+    if let Some(mv) = board.shortest_move_between(sq_select, sq) {
+        ClickAction::Play(mv)
+    } else if sq != sq_select && board.is_matching(board[sq_select], board[sq]) {
+        ClickAction::Blocked(sq_select, sq)
+    } else {
+        ClickAction::Deselect
+    }
+}
+
+/// マスの大きさ(px)。キャンバス上の座標とマスの対応は全てこれを通して計算する。
+#[derive(Clone, Copy, Debug)]
+struct TileSize {
+    width: u32,
+    height: u32,
+}
+
+impl TileSize {
+    /// マスに描画する牌の矩形 (x, y, 幅, 高さ) を返す。
+    fn tile_rect(self, sq: Square) -> (f64, f64, f64, f64) {
+        let (x, y) = self.square_origin(sq);
+        let margin = f64::from(TILE_MARGIN);
+
+        (
+            f64::from(x + TILE_MARGIN),
+            f64::from(y + TILE_MARGIN),
+            f64::from(self.width) - 2.0 * margin,
+            f64::from(self.height) - 2.0 * margin,
+        )
+    }
+
+    /// 盤面全体 (外周を含む) を描画するキャンバスの大きさ (幅, 高さ) を返す。
+    fn canvas_size(self, board: &Board) -> (u32, u32) {
+        let ncol = u32::try_from(board.ncol().get()).unwrap();
+        let nrow = u32::try_from(board.nrow().get()).unwrap();
+
+        (self.width * ncol, self.height * nrow)
+    }
+
+    /// マスの左上の座標を返す。
+    fn square_origin(self, sq: Square) -> (u32, u32) {
+        let c = u32::try_from(sq.c).unwrap();
+        let r = u32::try_from(sq.r).unwrap();
+
+        (self.width * c, self.height * r)
+    }
+
+    /// マスの中心の座標を返す。
+    fn square_center(self, sq: Square) -> (f64, f64) {
+        let (x, y) = self.square_origin(sq);
+
+        let x = f64::from(x) + f64::from(self.width) / 2.0;
+        let y = f64::from(y) + f64::from(self.height) / 2.0;
+
+        (x, y)
+    }
+
+    /// キャンバス上の座標 (x, y) を含むマスを返す。盤面外なら None を返す。
+    ///
+    /// マスは `square_origin()` からマスの大きさの範囲で、牌の周囲の余白も含む。
+    /// 余白は隣接するマスと重ならないので、境界付近の座標も描画されている牌のマスに対応する。
+    fn square_at(self, x: i32, y: i32, ncol: usize, nrow: usize) -> Option<Square> {
+        // x または y が負なら None を返す。
+        let x = u32::try_from(x).ok()?;
+        let y = u32::try_from(y).ok()?;
+
+        let c = usize::try_from(x / self.width).unwrap();
+        let r = usize::try_from(y / self.height).unwrap();
+
+        if c >= ncol || r >= nrow {
+            return None;
+        }
+
+        Some(Square::new(c, r))
+    }
+
+    /// 幅と高さを `factor` で割った大きさを返す。
+    fn scaled_down(self, factor: u32) -> Self {
+        Self {
+            width: self.width / factor,
+            height: self.height / factor,
+        }
+    }
+}
+
+/// キャンバスの内部解像度 (幅, 高さ) を返す。`size` はキャンバスの `canvas_size()`。
+///
+/// 高 DPI のディスプレイでぼやけないよう、`size` に `devicePixelRatio` を掛ける。
+/// CSS 上の大きさは `size` のままにする。
+fn canvas_backing_size(size: (u32, u32)) -> (u32, u32) {
+    let dpr = util::window().device_pixel_ratio();
+    let scale = |x: u32| (f64::from(x) * dpr).round() as u32;
+
+    (scale(size.0), scale(size.1))
+}
+
+/// 2D コンテキストを返す。座標は内部解像度によらず `canvas_size()` の単位で指定できるよう、
+/// 内部解像度に合わせて拡大しておく。`size` はキャンバスの `canvas_size()`。
+///
+/// 状態遷移の直後などでキャンバスの要素がまだ DOM に結び付いていない場合や、
+/// コンテキストを取得できない場合は、ログを出して None を返す。描画側はその回の描画を省く。
+fn scaled_context_2d(
+    el_canvas: &ElRef<HtmlCanvasElement>,
+    size: (u32, u32),
+) -> Option<CanvasRenderingContext2d> {
+    let Some(canvas) = el_canvas.get() else {
+        log!("canvas element is not attached; skip drawing");
+        return None;
+    };
+    let Some(ctx) = canvas
+        .get_context("2d")
+        .ok()
+        .flatten()
+        .and_then(|ctx| ctx.dyn_into::<CanvasRenderingContext2d>().ok())
+    else {
+        log!("cannot get 2d context of canvas; skip drawing");
+        return None;
+    };
+
+    let scale = f64::from(canvas.width()) / f64::from(size.0);
+    ctx.set_transform(scale, 0.0, 0.0, scale, 0.0, 0.0).unwrap();
+
+    Some(ctx)
+}
+
+/// タッチ点のキャンバス上の座標を、`canvas_size()` の単位で返す。
+///
+/// `size` はキャンバスの `canvas_size()`。キャンバスが CSS で拡大縮小されている場合も正しく変換する。
+fn touch_pos_on_canvas(canvas: &HtmlCanvasElement, size: (u32, u32), touch: &Touch) -> (i32, i32) {
+    let rect = canvas.get_bounding_client_rect();
+    let scale_x = f64::from(size.0) / rect.width();
+    let scale_y = f64::from(size.1) / rect.height();
+
+    let x = (f64::from(touch.client_x()) - rect.left()) * scale_x;
+    let y = (f64::from(touch.client_y()) - rect.top()) * scale_y;
+
+    (x.floor() as i32, y.floor() as i32)
+}
+
+/// 取った牌の割合 (0.0..=1.0) を示す横長のバー。
+fn view_progress_bar(ratio: f64) -> Node<Msg> {
+    div![
+        C!["progress"],
+        div![
+            C!["progress-fill"],
+            style! { St::Width => percent(100.0 * ratio.clamp(0.0, 1.0)) },
+        ],
+    ]
+}
+
+/// ゲームの終了を、埋め込み先のページ向けに `window` への `CustomEvent` で通知する。
+///
+/// `detail` は `{ elapsedMs: number, moves: number }` で、経過時間(ミリ秒)と着手数を表す。
+/// 着手数はシャッフル後からの数。
+fn dispatch_game_end(name: &str, elapsed: Duration, moves: usize) {
+    let detail = js_sys::Object::new();
+    let set = |key: &str, value: f64| {
+        js_sys::Reflect::set(&detail, &JsValue::from(key), &JsValue::from(value)).unwrap();
+    };
+    set("elapsedMs", elapsed.as_millis() as f64);
+    set("moves", moves as f64);
+
+    util::dispatch_custom_event(name, &detail);
+}
+
+/// 背景を描画する。
+///
+/// テーマによっては、羅紗のように中央を明るくした放射状のグラデーションで描画する。
+fn draw_background(ctx: &CanvasRenderingContext2d, theme: &str, width: u32, height: u32) {
+    let (width, height) = (f64::from(width), f64::from(height));
+
+    if asset::has_gradient_background(theme) {
+        let (cx, cy) = (width / 2.0, height / 2.0);
+        let radius = cx.hypot(cy);
+        let gradient = ctx
+            .create_radial_gradient(cx, cy, 0.0, cx, cy, radius)
+            .unwrap();
+        gradient.add_color_stop(0.0, "rgb(0, 150, 75)").unwrap();
+        gradient.add_color_stop(1.0, "rgb(0, 100, 50)").unwrap();
+        ctx.set_fill_style(&gradient);
+    } else {
+        ctx.set_fill_style(&JsValue::from("rgb(0, 128, 64)"));
+    }
+    ctx.fill_rect(0.0, 0.0, width, height);
+}
+
+/// 盤面の牌を描画する。
+fn draw_board_tiles(
+    ctx: &CanvasRenderingContext2d,
+    asset: &Asset,
+    tile_size: TileSize,
+    board: &Board,
+    rounded: bool,
+) {
+    for sq in board.squares_inner() {
+        if let BoardCell::Tile(tile) = board[sq] {
+            draw_tile(
+                ctx,
+                asset.image_tile(tile),
+                tile_size.tile_rect(sq),
+                rounded,
+            );
+        }
+    }
+}
+
+/// 盤面の外周のマスを暗く描画する。描画のみで、経路の判定には影響しない。
+fn draw_border(ctx: &CanvasRenderingContext2d, tile_size: TileSize, board: &Board) {
+    let ncol = board.ncol().get();
+    let nrow = board.nrow().get();
+
+    ctx.set_fill_style(&JsValue::from("rgb(0, 96, 48)"));
+    for sq in board.squares() {
+        if sq.c == 0 || sq.r == 0 || sq.c == ncol - 1 || sq.r == nrow - 1 {
+            let (x, y) = tile_size.square_origin(sq);
+            ctx.fill_rect(
+                f64::from(x),
+                f64::from(y),
+                f64::from(tile_size.width),
+                f64::from(tile_size.height),
+            );
+        }
+    }
+}
+
+/// 矩形 `rect` (x, y, 幅, 高さ) に牌の画像を描画する。
+///
+/// `rounded` なら、影付きの角丸の下地を描き、画像も角丸に切り抜く。
+/// 影の設定は描画後に元に戻すので、以降の描画には影響しない。
+fn draw_tile(
+    ctx: &CanvasRenderingContext2d,
+    img: &ImageBitmap,
+    rect: (f64, f64, f64, f64),
+    rounded: bool,
+) {
+    let (x, y, w, h) = rect;
+
+    if !rounded {
+        ctx.draw_image_with_image_bitmap_and_dw_and_dh(img, x, y, w, h)
+            .unwrap();
+        return;
+    }
+
+    ctx.save();
+    rounded_rect_path(ctx, x, y, w, h, TILE_RADIUS);
+    ctx.set_shadow_color("rgba(0, 0, 0, 0.5)");
+    ctx.set_shadow_blur(4.0);
+    ctx.set_shadow_offset_x(2.0);
+    ctx.set_shadow_offset_y(2.0);
+    ctx.set_fill_style(&JsValue::from("ivory"));
+    ctx.fill();
+    ctx.restore();
+
+    ctx.save();
+    rounded_rect_path(ctx, x, y, w, h, TILE_RADIUS);
+    ctx.clip();
+    ctx.draw_image_with_image_bitmap_and_dw_and_dh(img, x, y, w, h)
+        .unwrap();
+    ctx.restore();
+}
+
+/// 角丸の矩形のパスを作る。
+fn rounded_rect_path(ctx: &CanvasRenderingContext2d, x: f64, y: f64, w: f64, h: f64, radius: f64) {
+    ctx.begin_path();
+    ctx.move_to(x + radius, y);
+    ctx.arc_to(x + w, y, x + w, y + h, radius).unwrap();
+    ctx.arc_to(x + w, y + h, x, y + h, radius).unwrap();
+    ctx.arc_to(x, y + h, x, y, radius).unwrap();
+    ctx.arc_to(x, y, x + w, y, radius).unwrap();
+    ctx.close_path();
+}
+
+/// 牌の左上隅に種類の番号を描画する。`rect` は牌の矩形 (x, y, 幅, 高さ)。
+///
+/// 牌の絵柄を見分けにくい場合の補助表示。
+fn draw_tile_kind(ctx: &CanvasRenderingContext2d, tile: usize, rect: (f64, f64, f64, f64)) {
+    let (x, y, _, h) = rect;
+    let size = (h / 4.0).floor();
+    let text = tile.to_string();
+
+    // 絵柄に関わらず読めるよう、暗い下地の上に白で描く。
+    ctx.set_fill_style(&JsValue::from("rgba(0, 0, 0, 0.6)"));
+    ctx.fill_rect(x, y, size * 1.3, size * 1.1);
+
+    ctx.set_font(&format!("bold {size}px sans-serif"));
+    ctx.set_text_baseline("top");
+    ctx.set_fill_style(&JsValue::from("white"));
+    ctx.fill_text(&text, x + size * 0.1, y + size * 0.1)
+        .unwrap();
+}
+
+/// 牌の種類に対応する経路の色を返す。種類ごとに色相が決まる。
+fn path_color(tile: usize) -> String {
+    let hue = tile % TILE_KIND_COUNT * 360 / TILE_KIND_COUNT;
+
+    format!("hsl({hue}, 100%, 50%)")
+}
+
 #[derive(Debug, Default)]
-struct ModelLoading {}
+struct ModelLoading {
+    settings: Settings,
+    /// 共有された盤面。None ならランダムな盤面で始める。
+    board: Option<Board>,
+    /// 共有された盤面上で再生するリプレイ。
+    replay: Option<Vec<(Square, Square)>>,
+    /// 牌の画像の (読み込み済みの数, 全体の数)。
+    progress: Option<(usize, usize)>,
+}
 
 impl ModelLoading {
-    fn update(self, msg: Msg, orders: &mut impl Orders<Msg>) -> Model {
+    fn new(
+        settings: Settings,
+        board: Option<Board>,
+        replay: Option<Vec<(Square, Square)>>,
+    ) -> Self {
+        Self {
+            settings,
+            board,
+            replay,
+            progress: None,
+        }
+    }
+
+    fn update(mut self, msg: Msg, orders: &mut impl Orders<Msg>) -> Model {
         match msg {
             Msg::AssetLoad(asset) => {
                 orders.after_next_render(|_| Msg::ModelInit);
-                return Model::Playing(ModelPlaying::new(asset));
+                let shared = self.board.is_some();
+                let mut model = match self.board {
+                    Some(board) => ModelPlaying::with_board(asset, self.settings, board),
+                    None => ModelPlaying::new(asset, self.settings),
+                };
+                if let Some(replay) = self.replay {
+                    model.start_replay(&replay);
+                }
+                // 共有された盤面で始める場合は保存されたゲームを無視する。
+                if !shared {
+                    model.saved = SavedGame::load();
+                }
+                return Model::Playing(Box::new(model));
+            }
+            Msg::AssetLoadError(message) => {
+                return Model::Error(ModelError::new(message, self));
+            }
+            Msg::AssetProgress(loaded, total) => {
+                self.progress = Some((loaded, total));
+            }
+            Msg::Timer | Msg::WindowBlur | Msg::WindowFocus => {}
+            _ => log!("unexpected message:", msg),
+        }
+
+        Model::Loading(self)
+    }
+
+    fn view(&self) -> Node<Msg> {
+        let loading = self.settings.lang().tr(Text::Loading);
+
+        match self.progress {
+            Some((loaded, total)) => div![format!("{loading} {loaded}/{total}")],
+            None => div![format!("{loading}...")],
+        }
+    }
+}
+
+/// アセットの読み込みに失敗した状態。
+#[derive(Debug)]
+struct ModelError {
+    message: String,
+    /// 再試行時に戻る読み込み中の状態。
+    loading: ModelLoading,
+}
+
+impl ModelError {
+    fn new(message: String, loading: ModelLoading) -> Self {
+        Self { message, loading }
+    }
+
+    fn update(self, msg: Msg, orders: &mut impl Orders<Msg>) -> Model {
+        match msg {
+            Msg::Retry => {
+                load_asset(orders, &self.loading.settings);
+                let mut loading = self.loading;
+                loading.progress = None;
+                return Model::Loading(loading);
+            }
+            Msg::Timer | Msg::WindowBlur | Msg::WindowFocus => {}
+            _ => log!("unexpected message:", msg),
+        }
+
+        Model::Error(self)
+    }
+
+    fn view(&self) -> Node<Msg> {
+        let lang = self.loading.settings.lang();
+
+        div![
+            C!["ui"],
+            div![lang.tr(Text::CannotLoadAsset), &self.message],
+            div![button![lang.tr(Text::Retry), ev(Ev::Click, |_| Msg::Retry)]],
+        ]
+    }
+}
+
+#[derive(Debug)]
+struct ModelPlaying {
+    asset: Asset,
+    settings: Settings,
+    board: Board,
+    /// 開始時の盤面。同じ盤面で遊び直す場合に使う。
+    ///
+    /// シードではなく盤面そのものを持つ。URL で共有された盤面や保存から再開した盤面には
+    /// シードがないが、これなら同じように遊び直せる。
+    board_initial: Board,
+    /// 開始時の牌の数。シャッフルしても変わらない。進捗の表示に使う。
+    tiles_initial: usize,
+    /// 経過時間。最初の操作を待っている間は止まっているが、操作は受け付ける。
+    clock: PlayClock,
+    /// 開始前のカウントダウンの残りティック数。カウントダウン中のみ Some。
+    ///
+    /// カウントダウン中は `clock` を一時停止しておき、終わったら計測を始める。
+    /// 最初の操作を待つ設定の場合は、カウントダウンの後さらに最初の操作まで待つ。
+    start_countdown: Option<u32>,
+    /// ウィンドウがフォーカスを失ったために自動で一時停止しているかどうか。
+    /// 手動で一時停止している場合は false。
+    auto_paused: bool,
+    sq_select: Option<Square>,
+    /// 選択中の牌と、それから取れる同じ種類の牌のマス。選択が変わったら求め直す。
+    sqs_target: Option<(Square, Vec<Square>)>,
+    sq_hover: Option<Square>,
+    mv_preview: Option<Move>,
+    /// 最終手とそれで取った牌の種類。
+    mv_last: Option<(Move, usize)>,
+    /// 最終手の経路の表示を開始した時刻。表示中のみ Some。
+    path_since: Option<Instant>,
+    /// ヒントとして強調表示する着手。
+    mv_hint: Option<Move>,
+    /// 残りのヒントの数。
+    hints_left: u32,
+    /// 種類は合っているが経路がない牌の組。`ERROR_FLASH_DURATION` の間だけ赤く表示する。
+    sqs_error: Option<(Square, Square)>,
+    /// `sqs_error` の表示を開始した時刻。
+    error_since: Option<Instant>,
+    /// 現在の盤面を空にできるかどうか。計算が重いので要求されたときのみ調べる。
+    solvable: Option<bool>,
+    /// 合法手は残っているが盤面を空にできなくなったかどうか。
+    /// `Settings::warn_dead_end` が有効な場合のみ着手後に調べる。
+    dead_end: bool,
+    /// 牌の種類の番号を表示するかどうか。
+    show_kinds: bool,
+    /// 種類ごとの残り枚数の一覧を表示するかどうか。
+    show_legend: bool,
+    /// 設定パネルを開いているかどうか。
+    show_settings: bool,
+    /// デバッグ用に、外周を含むマスの境界線と座標を表示するかどうか。
+    show_grid: bool,
+    /// これまでに行った着手。
+    moves: Vec<Move>,
+    /// `moves` の各着手で取った牌の種類。
+    move_kinds: Vec<usize>,
+    /// スコアの計算に使う集計値。
+    stats: PlayStats,
+    /// `moves` の各着手を行う直前の `stats`。着手の取り消しで戻すのに使う。
+    stats_history: Vec<PlayStats>,
+    /// 同じ種類の牌を続けて取った回数。コンボが途切れると 0 に戻る。
+    combo: u32,
+    /// 最後に取った牌の種類とその時刻。`COMBO_WINDOW` を過ぎると None に戻る。
+    combo_last: Option<(usize, Instant)>,
+    /// これまでに取った牌の数。エンドレスモードでは盤面をまたいで数える。
+    tiles_cleared: usize,
+    /// 再生中のリプレイ。再生中はクリックを受け付けない。
+    replay: Option<Replay>,
+    /// 進行中のタッチ操作。
+    touch: Option<TouchDrag>,
+    /// 前回クリックしたマスと時刻。ダブルクリックの判定に使う。
+    click_last: Option<(Square, Instant)>,
+    /// リプレイや保存されたゲームを読み込めなかった場合のエラー。
+    load_error: Option<String>,
+    /// 再開できる保存されたゲーム。起動後、最初の着手までの間のみ保持する。
+    saved: Option<SavedGame>,
+    el_canvas: ElRef<HtmlCanvasElement>,
+}
+
+/// 再生中のリプレイ。
+#[derive(Debug)]
+struct Replay {
+    /// 残りの着手。
+    moves: VecDeque<Move>,
+    /// 次の着手までの残りティック数。
+    timer: u32,
+    /// 自動で解いている場合 true。
+    auto: bool,
+}
+
+/// 進行中のタッチ操作。牌から牌へのドラッグで 1 組を取れる。
+#[derive(Debug)]
+struct TouchDrag {
+    /// 触れ始めたマス。
+    sq_start: Square,
+    /// 触れ始めたマスから指が離れたかどうか。
+    moved: bool,
+    /// 触れ始めたときに牌を選択したかどうか。
+    selected: bool,
+}
+
+/// ゲームの経過時間の計測。最初の操作まで計測を始めないようにできる。
+///
+/// 最初の操作を待っている間は、`resume()` しても計測を始めない (一時停止の解除や
+/// カウントダウンの終了より、最初の操作を待つ方が優先される)。計測は `start_if_waiting()` で始まる。
+#[derive(Debug)]
+struct PlayClock {
+    stopwatch: Stopwatch,
+    /// 最初の操作を待っていて、計測を始めていないかどうか。
+    waiting: bool,
+}
+
+impl PlayClock {
+    /// 計測中の時計を返す。
+    fn start() -> Self {
+        Self {
+            stopwatch: Stopwatch::start(),
+            waiting: false,
+        }
+    }
+
+    /// 経過時間 0 で一時停止中の時計を返す。
+    fn paused() -> Self {
+        Self {
+            stopwatch: Stopwatch::paused(),
+            waiting: false,
+        }
+    }
+
+    /// 経過時間 0 で最初の操作を待っている時計を返す。
+    fn waiting() -> Self {
+        Self {
+            stopwatch: Stopwatch::paused(),
+            waiting: true,
+        }
+    }
+
+    /// 既に `elapsed` だけ経過した状態から計測を開始した時計を返す。
+    fn start_with_elapsed(elapsed: Duration) -> Self {
+        Self {
+            stopwatch: Stopwatch::start_with_elapsed(elapsed),
+            waiting: false,
+        }
+    }
+
+    /// 経過時間を返す。一時停止中の時間は含まない。
+    fn elapsed(&self) -> Duration {
+        self.stopwatch.elapsed()
+    }
+
+    /// 計測中かどうかを返す。
+    fn is_running(&self) -> bool {
+        !self.stopwatch.is_paused()
+    }
+
+    /// 最初の操作を待っているかどうかを返す。
+    fn is_waiting(&self) -> bool {
+        self.waiting
+    }
+
+    /// 一時停止中かどうかを返す。最初の操作を待っている間は一時停止中とみなさない。
+    fn is_paused(&self) -> bool {
+        self.stopwatch.is_paused() && !self.waiting
+    }
+
+    /// 一時停止する。
+    fn pause(&mut self) {
+        self.stopwatch.pause();
+    }
+
+    /// 計測を再開する。最初の操作を待っている間は何もしない。
+    fn resume(&mut self) {
+        if !self.waiting {
+            self.stopwatch.resume();
+        }
+    }
+
+    /// 最初の操作を待っている場合、計測を始める。
+    fn start_if_waiting(&mut self) {
+        if self.waiting {
+            self.waiting = false;
+            self.stopwatch.resume();
+        }
+    }
+}
+
+impl ModelPlaying {
+    fn new(asset: Asset, settings: Settings) -> Self {
+        let board = settings.new_board();
+
+        Self::with_board(asset, settings, board)
+    }
+
+    fn with_board(asset: Asset, settings: Settings, board: Board) -> Self {
+        replace_url_query(&board, &settings);
+
+        let board_initial = board.clone();
+        let tiles_initial = board.tile_count();
+
+        let clock = PlayClock::start();
+        let show_kinds = settings.show_kinds;
+
+        Self {
+            asset,
+            settings,
+            board,
+            board_initial,
+            tiles_initial,
+            clock,
+            start_countdown: None,
+            auto_paused: false,
+            sq_select: None,
+            sqs_target: None,
+            sq_hover: None,
+            mv_preview: None,
+            mv_last: None,
+            path_since: None,
+            mv_hint: None,
+            hints_left: HINT_COUNT,
+            sqs_error: None,
+            error_since: None,
+            solvable: None,
+            dead_end: false,
+            show_kinds,
+            show_legend: false,
+            show_settings: false,
+            show_grid: false,
+            moves: Vec::new(),
+            move_kinds: Vec::new(),
+            stats: PlayStats::default(),
+            stats_history: Vec::new(),
+            combo: 0,
+            combo_last: None,
+            tiles_cleared: 0,
+            replay: None,
+            touch: None,
+            click_last: None,
+            load_error: None,
+            saved: None,
+            el_canvas: Default::default(),
+        }
+    }
+
+    /// 新しい盤面で始め直す。
+    fn restart(&mut self) {
+        self.next_board();
+        self.reset_game();
+    }
+
+    /// 開始時の盤面で始め直す。経過時間や着手数などは全てリセットする。
+    fn retry(&mut self) {
+        self.set_board(self.board_initial.clone());
+        self.reset_game();
+    }
+
+    /// 盤面以外のゲームの進行状況をリセットする。
+    fn reset_game(&mut self) {
+        SavedGame::clear();
+        self.saved = None;
+
+        self.clock = PlayClock::start();
+        self.start_countdown = None;
+        self.auto_paused = false;
+
+        self.tiles_cleared = 0;
+        self.hints_left = HINT_COUNT;
+        self.replay = None;
+        self.load_error = None;
+    }
+
+    /// 経過時間を返す。一時停止中の時間は含まない。
+    fn elapsed(&self) -> Duration {
+        self.clock.elapsed()
+    }
+
+    /// 操作を受け付けない一時停止中かどうかを返す。
+    ///
+    /// カウントダウン中は一時停止中とみなす。最初の操作を待っている間は `clock` が止まっていても
+    /// 一時停止中とはみなさない。
+    fn is_paused(&self) -> bool {
+        self.start_countdown.is_some() || self.clock.is_paused()
+    }
+
+    /// 新しい盤面に切り替える。経過時間と取った牌の数は引き継ぐ。
+    fn next_board(&mut self) {
+        self.set_board(self.settings.new_board());
+    }
+
+    /// 盤面を `board` に置き換え、盤面に関する状態をリセットする。
+    fn set_board(&mut self, board: Board) {
+        self.board_initial = board.clone();
+        self.tiles_initial = board.tile_count();
+        self.board = board;
+        replace_url_query(&self.board, &self.settings);
+
+        self.clear_transient();
+        self.solvable = None;
+        self.dead_end = false;
+        self.moves.clear();
+        self.move_kinds.clear();
+        self.stats = PlayStats::default();
+        self.stats_history.clear();
+        self.combo = 0;
+        self.combo_last = None;
+    }
+
+    /// 保存されたゲームを再開する。
+    /// 保存内容が不正な場合はエラーを返し、現在のゲームは変更しない。
+    fn resume(&mut self, saved: &SavedGame) -> anyhow::Result<()> {
+        let gravity = gravity_from_value(&saved.gravity)
+            .ok_or_else(|| anyhow!("saved game: invalid gravity '{}'", saved.gravity))?;
+        let mut settings = self.settings.clone();
+        settings.gravity = gravity;
+
+        let board_initial = Board::from_string_repr(&saved.board, settings.rule())?;
+
+        let moves = board_initial
+            .resolve_moves(&parse_replay(&saved.moves)?)
+            .context("saved game")?;
+        let mut board = board_initial.clone();
+        board.apply_moves(&moves).context("saved game")?;
+        // 牌を寄せるルールでは牌が移動するので、各着手の直前の盤面で牌種を調べる。
+        let move_kinds: Vec<usize> = moves
+            .iter()
+            .scan(board_initial.clone(), |board, mv| {
+                let BoardCell::Tile(kind) = board[mv.src()] else {
+                    panic!("move source should be a tile");
+                };
+                board.do_move(mv);
+                Some(kind)
+            })
+            .collect();
+
+        replace_url_query(&board_initial, &settings);
+
+        self.settings = settings;
+        self.board = board;
+        self.tiles_initial = board_initial.tile_count();
+        self.board_initial = board_initial;
+        self.clock = PlayClock::start_with_elapsed(Duration::from_millis(saved.elapsed_ms));
+        self.start_countdown = None;
+        self.auto_paused = false;
+        self.clear_transient();
+        self.solvable = None;
+        self.dead_end = false;
+        self.tiles_cleared = 2 * moves.len();
+        // 保存されたゲームの着手は全て最短経路で復元している。コンボは引き継がない。
+        self.stats = PlayStats {
+            shortest_used: moves.len(),
+            combos: 0,
+        };
+        self.stats_history = (0..moves.len())
+            .map(|i| PlayStats {
+                shortest_used: i,
+                combos: 0,
+            })
+            .collect();
+        self.combo = 0;
+        self.combo_last = None;
+        self.moves = moves;
+        self.move_kinds = move_kinds;
+        self.replay = None;
+        self.load_error = None;
+
+        Ok(())
+    }
+
+    /// 進行中のゲームを保存する。
+    fn store(&self) {
+        let saved = SavedGame {
+            board: self.board_initial.to_string_repr(),
+            gravity: gravity_value(self.settings.gravity).to_owned(),
+            moves: self.moves.iter().map(Move::to_string_repr).join(" "),
+            elapsed_ms: u64::try_from(self.elapsed().as_millis()).unwrap_or(u64::MAX),
+        };
+
+        saved.store();
+    }
+
+    /// リプレイの再生を開始する。
+    /// 着手の列が現在の盤面から再生できない場合、エラーを表示して再生しない。
+    fn start_replay(&mut self, endpoints: &[(Square, Square)]) {
+        let moves = match self.board.resolve_moves(endpoints) {
+            Ok(moves) => moves,
+            Err(e) => {
+                let lang = self.settings.lang();
+                self.load_error = Some(format!("{}{e:#}", lang.tr(Text::CannotPlayReplay)));
+                return;
+            }
+        };
+
+        self.sq_select = None;
+        self.replay = Some(Replay {
+            moves: moves.into(),
+            timer: REPLAY_INTERVAL,
+            auto: false,
+        });
+    }
+
+    /// 現在の盤面の解を求め、リプレイと同様に 1 手ずつ着手する。
+    /// 解がない場合は解けない旨を表示する。
+    fn start_auto_solve(&mut self) {
+        let Some(moves) = self.board.solve() else {
+            self.solvable = Some(false);
+            return;
+        };
+
+        self.sq_select = None;
+        self.mv_hint = None;
+        self.replay = Some(Replay {
+            moves: moves.into(),
+            timer: REPLAY_INTERVAL,
+            auto: true,
+        });
+    }
+
+    /// リプレイの次の着手を行う。
+    /// 着手は再生開始時に検証済みだが、念のため違法な場合はエラーを表示して再生を止める。
+    fn step_replay(mut self, orders: &mut impl Orders<Msg>) -> Model {
+        let replay = self.replay.as_mut().expect("replay should be some");
+
+        let mv = match replay.moves.pop_front() {
+            Some(mv) => mv,
+            None => {
+                self.replay = None;
+                return Model::Playing(Box::new(self));
+            }
+        };
+        replay.timer = REPLAY_INTERVAL;
+
+        if !self.board.is_legal(&mv) {
+            self.replay = None;
+            let lang = self.settings.lang();
+            self.load_error = Some(format!(
+                "{}{}",
+                lang.tr(Text::IllegalMoveInReplay),
+                mv.to_string_repr()
+            ));
+            return Model::Playing(Box::new(self));
+        }
+
+        self.play_move(mv, orders)
+    }
+
+    /// 着手を行う。クリアまたは手詰まりになった場合、対応する状態に遷移する。
+    fn play_move(mut self, mv: Move, orders: &mut impl Orders<Msg>) -> Model {
+        self.clock.start_if_waiting();
+        self.stats_history.push(self.stats);
+        let shortest = self
+            .board
+            .shortest_move_between(mv.src(), mv.dst())
+            .map_or(0, |mv| mv.distance());
+        if mv.distance() == shortest {
+            self.stats.shortest_used += 1;
+        }
+        let BoardCell::Tile(tile) = self.board[mv.src()] else {
+            panic!("move source should be a tile");
+        };
+        // 効果音の変種は取った牌の種類で選ぶ。
+        self.asset.play_pick(tile);
+
+        // 前の着手と同じ種類の牌を `COMBO_WINDOW` 以内に取ればコンボ。
+        let now = Instant::now();
+        self.combo = match self.combo_last {
+            Some((kind, at)) if kind == tile && now - at <= COMBO_WINDOW => self.combo + 1,
+            _ => 1,
+        };
+        if self.combo >= 2 {
+            self.stats.combos += 1;
+        }
+        self.combo_last = Some((tile, now));
+
+        self.board.do_move(&mv);
+        self.moves.push(mv.clone());
+        self.move_kinds.push(tile);
+        self.tiles_cleared += 2;
+        self.mv_last = Some((mv, tile));
+        // アニメーションを抑制する場合、経路は表示せず牌を消すだけにする。
+        self.path_since = (!self.settings.reduced_motion()).then_some(now);
+        self.sq_select = None;
+        self.sqs_target = None;
+        self.mv_hint = None;
+        self.sqs_error = None;
+        self.error_since = None;
+        self.solvable = None;
+        self.dead_end = false;
+
+        // 以前に保存されたゲームはこれで上書きされる。
+        self.saved = None;
+
+        // クリア判定。エンドレスモードでは新しい盤面で続ける。
+        if self.board.is_empty() && self.settings.endless {
+            self.next_board();
+            self.store();
+            orders.after_next_render(|_| Msg::DrawCanvas);
+            return Model::Playing(Box::new(self));
+        }
+        if self.board.is_empty() {
+            SavedGame::clear();
+            dispatch_game_end(EVENT_WIN, self.clock.elapsed(), self.moves.len());
+            orders.after_next_render(|_| Msg::ModelInit);
+            return Model::Win(ModelWin::new(
+                self.asset,
+                self.settings,
+                self.board_initial,
+                self.clock.elapsed(),
+                self.moves,
+                HINT_COUNT - self.hints_left,
+                self.stats,
+            ));
+        }
+
+        // stuck 判定。
+        if self.board.is_stuck() {
+            SavedGame::clear();
+            dispatch_game_end(EVENT_STUCK, self.clock.elapsed(), self.moves.len());
+            orders.after_next_render(|_| Msg::ModelInit);
+            return Model::Stuck(ModelStuck::new(
+                self.asset,
+                self.settings,
+                self.board,
+                self.board_initial,
+                self.clock.elapsed(),
+                self.show_kinds,
+                false,
+            ));
+        }
+
+        // クリア/stuck 判定の後で、同じ種類の残りの牌を選択する。リプレイ中は選択しない。
+        if self.settings.continuous_match && self.replay.is_none() {
+            self.sq_select = self.next_select_of_kind(tile);
+        }
+
+        self.store();
+
+        self.update_preview();
+        orders.after_next_render(|_| Msg::DrawCanvas);
+        // 重い処理なので、着手を描画してから調べる。
+        if self.settings.warn_dead_end && self.replay.is_none() {
+            orders.after_next_render(|_| Msg::CheckDeadEnd);
+        }
+
+        Model::Playing(Box::new(self))
+    }
+
+    /// 連続取りモードで次に選択する、種類 `kind` の牌のマスを返す。
+    ///
+    /// 今すぐ取れる牌を優先する。同じ種類の牌が残っていなければ None を返す。
+    fn next_select_of_kind(&self, kind: usize) -> Option<Square> {
+        let sqs = self.board.squares_of_kind(kind);
+
+        sqs.iter()
+            .copied()
+            .find(|&sq1| sqs.iter().any(|&sq2| self.board.are_connectable(sq1, sq2)))
+            .or_else(|| sqs.first().copied())
+    }
+
+    /// 盤面上の牌を、解を持つようにシャッフルする。
+    ///
+    /// シャッフル前の着手からは現在の盤面を再現できないので、
+    /// シャッフル後の盤面から始めたものとみなす。経過時間や集計値はそのまま引き継ぐ。
+    fn shuffle(&mut self) {
+        // どう並べても解けない盤面 (牌を置けないマスに阻まれている場合) はそのままにする。
+        if self.board.shuffle_solvable().is_err() {
+            self.solvable = Some(false);
+            return;
+        }
+        self.board_initial = self.board.clone();
+        self.moves.clear();
+        self.move_kinds.clear();
+        self.stats_history.clear();
+        replace_url_query(&self.board, &self.settings);
+
+        self.clear_transient();
+        self.solvable = None;
+        self.dead_end = false;
+
+        self.store();
+    }
+
+    /// 最後の着手を取り消す。取り消せる着手がなければ何もしない。
+    ///
+    /// 開始時の盤面から残りの着手をやり直して盤面を戻す (牌を寄せるルールでも正しく戻せる)。
+    /// 集計値は着手前の値に戻し、コンボは途切れさせる。経過時間は戻さない。
+    /// シャッフルや新しい盤面より前の着手は取り消せない。
+    fn undo(&mut self) {
+        let Some(stats) = self.stats_history.pop() else {
+            return;
+        };
+        self.moves.pop();
+        self.move_kinds.pop();
+
+        let mut board = self.board_initial.clone();
+        board
+            .apply_moves(&self.moves)
+            .expect("moves played so far should be legal");
+        self.board = board;
+
+        self.stats = stats;
+        self.tiles_cleared -= 2;
+        self.combo = 0;
+        self.combo_last = None;
+        self.clear_transient();
+        self.solvable = None;
+        self.dead_end = false;
+        self.saved = None;
+
+        self.store();
+    }
+
+    /// 選択やプレビュー、経路の表示などの一時的な状態を消す。
+    /// 盤面が着手以外の方法で変わるときに呼び、古い状態が描画されないようにする。
+    fn clear_transient(&mut self) {
+        self.sq_select = None;
+        self.sqs_target = None;
+        self.mv_preview = None;
+        self.mv_last = None;
+        self.path_since = None;
+        self.mv_hint = None;
+        self.sqs_error = None;
+        self.error_since = None;
+        self.touch = None;
+        self.click_last = None;
+    }
+
+    /// ラッシュモードの制限時間切れで終了する。
+    fn time_up(self, orders: &mut impl Orders<Msg>) -> Model {
+        SavedGame::clear();
+        dispatch_game_end(EVENT_TIME_UP, self.clock.elapsed(), self.moves.len());
+        orders.after_next_render(|_| Msg::ModelInit);
+
+        Model::Stuck(ModelStuck::new(
+            self.asset,
+            self.settings,
+            self.board,
+            self.board_initial,
+            self.clock.elapsed(),
+            self.show_kinds,
+            true,
+        ))
+    }
+
+    /// 選択中の牌とポインタ下の牌を結ぶ経路のプレビューを更新する。
+    /// 再描画が必要かどうかを返す。
+    fn update_preview(&mut self) -> bool {
+        self.update_targets();
+
+        let preview = match (self.sq_select, self.sq_hover) {
+            (Some(sq_select), Some(sq_hover)) => {
+                self.board.shortest_move_between(sq_select, sq_hover)
+            }
+            _ => None,
+        };
+
+        let changed = self.mv_preview.is_some() || preview.is_some();
+        self.mv_preview = preview;
+
+        changed
+    }
+
+    /// 選択中の牌から取れる同じ種類の牌を求め直す。選択が変わっていなければ何もしない。
+    fn update_targets(&mut self) {
+        let sq_select = self.sq_select.filter(|_| self.settings.show_targets);
+        if self.sqs_target.as_ref().map(|e| e.0) == sq_select {
+            return;
+        }
+
+        self.sqs_target = sq_select.map(|sq_select| {
+            let BoardCell::Tile(kind) = self.board[sq_select] else {
+                panic!("selected square should be a tile");
+            };
+            let sqs = self
+                .board
+                .squares_of_kind(kind)
+                .into_iter()
+                .filter(|&sq| self.board.are_connectable(sq_select, sq))
+                .collect();
+            (sq_select, sqs)
+        });
+    }
+
+    /// マスをクリック (タップ) したときの処理。
+    ///
+    /// 選択中の牌から取れる牌なら取る。牌のないマスの場合は選択を解除する。
+    fn click_square(mut self, sq: Option<Square>, orders: &mut impl Orders<Msg>) -> Model {
+        if sq.is_some_and(|sq| self.board[sq].is_tile()) {
+            self.clock.start_if_waiting();
+        }
+
+        match click_action(&self.board, self.sq_select, sq) {
+            ClickAction::Play(mv) => return self.play_move(mv, orders),
+            ClickAction::Select(sq) => {
+                self.asset.play_select();
+                self.sq_select = Some(sq);
+            }
+            ClickAction::Blocked(sq1, sq2) => {
+                // 取れる牌同士なのに経路がない場合、両方の牌を赤く点滅させる。
+                self.sqs_error = Some((sq1, sq2));
+                self.error_since = Some(Instant::now());
+                self.sq_select = None;
+            }
+            ClickAction::Deselect => self.sq_select = None,
+            ClickAction::Ignore => return Model::Playing(Box::new(self)),
+        }
+        self.update_preview();
+        orders.after_next_render(|_| Msg::DrawCanvas);
+
+        Model::Playing(Box::new(self))
+    }
+
+    /// ダブルクリックされた牌を、同じ種類の牌のうち経路が最も短いものと取る。
+    ///
+    /// 取れる牌がなければその牌を選択するだけにする。牌のないマスなら通常のクリックとして扱う。
+    fn double_click_square(mut self, sq: Square, orders: &mut impl Orders<Msg>) -> Model {
+        let BoardCell::Tile(kind) = self.board[sq] else {
+            return self.click_square(Some(sq), orders);
+        };
+
+        let mv = self
+            .board
+            .squares_of_kind(kind)
+            .into_iter()
+            .filter(|&sq_other| sq_other != sq)
+            .filter_map(|sq_other| self.board.shortest_move_between(sq, sq_other))
+            .min_by_key(|mv| mv.distance());
+        if let Some(mv) = mv {
+            return self.play_move(mv, orders);
+        }
+
+        if self.sq_select != Some(sq) {
+            self.asset.play_select();
+            self.sq_select = Some(sq);
+        }
+        self.update_preview();
+        orders.after_next_render(|_| Msg::DrawCanvas);
+
+        Model::Playing(Box::new(self))
+    }
+
+    fn update(mut self, msg: Msg, orders: &mut impl Orders<Msg>) -> Model {
+        match msg {
+            Msg::Restart => {
+                orders.after_next_render(|_| Msg::ModelInit);
+                // XXX: 新しい Model::Playing を返すと el_ref() が効かない問題が起こるので...
+                self.restart();
+            }
+            Msg::ReplayBoard => {
+                orders.after_next_render(|_| Msg::ModelInit);
+                self.retry();
+            }
+            Msg::ResumeSaved => {
+                if let Some(saved) = self.saved.take() {
+                    if let Err(e) = self.resume(&saved) {
+                        SavedGame::clear();
+                        let lang = self.settings.lang();
+                        self.load_error =
+                            Some(format!("{}{e:#}", lang.tr(Text::CannotResumeSavedGame)));
+                    }
+                    orders.after_next_render(|_| Msg::DrawCanvas);
+                }
+            }
+            Msg::Undo if self.is_paused() || self.replay.is_some() => {}
+            Msg::Undo => {
+                self.undo();
+                orders.after_next_render(|_| Msg::DrawCanvas);
+            }
+            Msg::Hint | Msg::SmartHint
+                if self.is_paused() || self.replay.is_some() || self.hints_left == 0 => {}
+            Msg::Hint => {
+                self.clock.start_if_waiting();
+                self.hints_left -= 1;
+                self.mv_hint = self.board.find_move();
+                orders.after_next_render(|_| Msg::DrawCanvas);
+            }
+            Msg::SmartHint => {
+                self.clock.start_if_waiting();
+                self.hints_left -= 1;
+                self.mv_hint = self.board.safe_move();
+                orders.after_next_render(|_| Msg::DrawCanvas);
+            }
+            Msg::CheckSolvable => {
+                self.solvable = Some(self.board.is_solvable());
+            }
+            Msg::CheckDeadEnd => {
+                // 合法手がない場合は stuck として扱われるので、ここでは調べない。
+                if self.board.find_move().is_some() {
+                    let solvable = self.board.is_solvable();
+                    self.solvable = Some(solvable);
+                    self.dead_end = !solvable;
+                }
+            }
+            Msg::Shuffle if self.is_paused() || self.replay.is_some() => {}
+            Msg::Shuffle => {
+                self.shuffle();
+                orders.after_next_render(|_| Msg::DrawCanvas);
+            }
+            Msg::AutoSolve if self.is_paused() || self.replay.is_some() => {}
+            Msg::AutoSolve => {
+                self.start_auto_solve();
+                orders.after_next_render(|_| Msg::DrawCanvas);
+            }
+            // カウントダウン中や最初の操作を待っている間は、一時停止/再開できない。
+            Msg::Pause | Msg::Resume
+                if self.start_countdown.is_some() || self.clock.is_waiting() => {}
+            Msg::Pause => {
+                self.clock.pause();
+                orders.after_next_render(|_| Msg::DrawCanvas);
+            }
+            Msg::Resume => {
+                self.clock.resume();
+                self.auto_paused = false;
+                orders.after_next_render(|_| Msg::DrawCanvas);
+            }
+            // 手動で一時停止している場合は、フォーカスが戻っても再開しない。
+            Msg::WindowBlur if !self.clock.is_running() => {}
+            Msg::WindowBlur => {
+                self.clock.pause();
+                self.auto_paused = true;
+                orders.after_next_render(|_| Msg::DrawCanvas);
+            }
+            Msg::WindowFocus if !self.auto_paused => {}
+            Msg::WindowFocus => {
+                self.clock.resume();
+                self.auto_paused = false;
+                orders.after_next_render(|_| Msg::DrawCanvas);
+            }
+            Msg::GravityChange(value) => {
+                if let Some(gravity) = gravity_from_value(&value) {
+                    // ルールが変わるので新しい盤面で始め直す。
+                    self.settings.gravity = gravity;
+                    self.settings.store();
+                    orders.after_next_render(|_| Msg::ModelInit);
+                    self.restart();
+                }
+            }
+            Msg::SymmetryChange(value) => {
+                if let Some(symmetry) = symmetry_from_value(&value) {
+                    // 盤面の生成方法が変わるので新しい盤面で始め直す。
+                    self.settings.symmetry = symmetry;
+                    self.settings.store();
+                    orders.after_next_render(|_| Msg::ModelInit);
+                    self.restart();
+                }
+            }
+            Msg::RushChange(value) => {
+                if let Ok(secs) = value.parse::<u64>() {
+                    // 制限時間は経過時間に対して判定するので、新しい盤面で始め直す。
+                    self.settings.rush_limit = (secs > 0).then(|| Duration::from_secs(secs));
+                    orders.after_next_render(|_| Msg::ModelInit);
+                    self.restart();
+                }
+            }
+            Msg::DifficultyChange(value) => {
+                if let Some(difficulty) = difficulty_from_value(&value) {
+                    // 牌種の数が変わるとアセットを読み込み直す必要があるので、
+                    // 読み込み中の状態から新しい盤面で始め直す。
+                    self.settings.set_difficulty(difficulty);
+                    self.settings.store();
+                    load_asset(orders, &self.settings);
+                    return Model::Loading(ModelLoading::new(self.settings, None, None));
+                }
+            }
+            Msg::ThemeChange(theme) => {
+                // 読み込みが終わるまでは現在のアセットで描画を続ける。
+                self.settings.theme = theme;
+                self.settings.store();
+                load_asset(orders, &self.settings);
+            }
+            Msg::AssetLoad(asset) => {
+                // 読み込み中にテーマが再度変更された場合、古い読み込み結果は捨てる。
+                if asset.theme() == self.settings.theme {
+                    self.asset = asset;
+                    orders.after_next_render(|_| Msg::DrawCanvas);
+                }
+            }
+            Msg::PathDurationChange(value) => {
+                if let Ok(ms) = value.parse::<u32>() {
+                    self.settings.path_duration_ms = ms.min(PATH_DURATION_MS_MAX);
+                    self.settings.store();
+                }
+            }
+            Msg::EndlessToggle => {
+                self.settings.endless = !self.settings.endless;
+            }
+            Msg::RoundedTilesToggle => {
+                self.settings.rounded_tiles = !self.settings.rounded_tiles;
+                self.settings.store();
+                orders.after_next_render(|_| Msg::DrawCanvas);
+            }
+            Msg::ShowKindsToggle => {
+                // 次のゲームでも同じ表示にする。
+                self.show_kinds = !self.show_kinds;
+                self.settings.show_kinds = self.show_kinds;
+                self.settings.store();
+                orders.after_next_render(|_| Msg::DrawCanvas);
+            }
+            Msg::BorderToggle => {
+                self.settings.show_border = !self.settings.show_border;
+                self.settings.store();
+                orders.after_next_render(|_| Msg::DrawCanvas);
+            }
+            Msg::ClockOnFirstMoveToggle => {
+                self.settings.clock_on_first_move = !self.settings.clock_on_first_move;
+                self.settings.store();
+            }
+            Msg::CountdownToggle => {
+                self.settings.countdown = !self.settings.countdown;
+                self.settings.store();
+            }
+            Msg::ShowTargetsToggle => {
+                self.settings.show_targets = !self.settings.show_targets;
+                self.settings.store();
+                self.update_targets();
+                orders.after_next_render(|_| Msg::DrawCanvas);
+            }
+            Msg::ContinuousMatchToggle => {
+                self.settings.continuous_match = !self.settings.continuous_match;
+                self.settings.store();
+            }
+            Msg::WarnDeadEndToggle => {
+                self.settings.warn_dead_end = !self.settings.warn_dead_end;
+                self.settings.store();
+            }
+            Msg::SettingsToggle => {
+                self.show_settings = !self.show_settings;
+            }
+            Msg::ReducedMotionChange(value) => {
+                self.settings.reduced_motion_override = match value.as_str() {
+                    "reduce" => Some(true),
+                    "full" => Some(false),
+                    _ => None,
+                };
+                self.settings.store();
+                if self.settings.reduced_motion() {
+                    self.path_since = None;
+                    orders.after_next_render(|_| Msg::DrawCanvas);
+                }
+            }
+            Msg::LangChange(value) => {
+                self.settings.lang_override = lang_from_value(&value);
+                self.settings.store();
+            }
+            Msg::TileAspectChange(value) => {
+                if let Some(tile_aspect) = tile_aspect_from_value(&value) {
+                    // キャンバスの大きさが変わるので描き直す。
+                    self.settings.tile_aspect = tile_aspect;
+                    self.settings.store();
+                    orders.after_next_render(|_| Msg::DrawCanvas);
+                }
+            }
+            Msg::HistoryFlash(_) if self.replay.is_some() => {}
+            Msg::HistoryFlash(i) => {
+                // 履歴の着手の経路を、最終手と同じように一時的に表示する。
+                if let Some(mv) = self.moves.get(i) {
+                    self.mv_last = Some((mv.clone(), self.move_kinds[i]));
+                    self.path_since = (!self.settings.reduced_motion()).then(Instant::now);
+                    orders.after_next_render(|_| Msg::DrawCanvas);
+                }
             }
-            Msg::Timer => {}
-            _ => panic!("unexpected message: {msg:?}"),
-        }
-
-        Model::Loading(self)
-    }
-
-    fn view(&self) -> Node<Msg> {
-        div!["loading..."]
-    }
-}
-
-#[derive(Debug)]
-struct ModelPlaying {
-    asset: Asset,
-    board: Board,
-    clock: Instant,
-    sq_select: Option<Square>,
-    mv_last: Option<Move>,
-    path_timer: u32,
-    el_canvas: ElRef<HtmlCanvasElement>,
-}
-
-impl ModelPlaying {
-    fn new(asset: Asset) -> Self {
-        let board = Board::random(
-            NonZeroUsize::new(NCOL_INNER).unwrap(),
-            NonZeroUsize::new(NROW_INNER).unwrap(),
-        );
+            Msg::KindLegendToggle => {
+                self.show_legend = !self.show_legend;
+            }
+            Msg::DebugGridToggle => {
+                self.show_grid = !self.show_grid;
+                orders.after_next_render(|_| Msg::DrawCanvas);
+            }
+            Msg::MuteToggle => {
+                let muted = self.asset.is_muted();
+                self.asset.set_muted(!muted);
+            }
+            Msg::VolumeChange(value) => {
+                if let Ok(volume) = value.parse::<f64>() {
+                    self.asset.set_volume(volume);
+                }
+            }
+            // テーマ切り替え時の読み込み失敗は無視し、現在のアセットを使い続ける。
+            Msg::AssetProgress(..) | Msg::AssetLoadError(_) => {}
+            Msg::ModelInit => {
+                // リプレイはそのまま再生する。
+                if self.replay.is_none() {
+                    if self.settings.countdown {
+                        self.clock = PlayClock::paused();
+                        self.start_countdown = Some(COUNTDOWN_TICKS);
+                    }
+                    if self.settings.clock_on_first_move {
+                        self.clock = PlayClock::waiting();
+                    }
+                }
+                orders.after_next_render(|_| Msg::DrawCanvas);
+            }
+            Msg::Timer => {
+                if let Some(ticks) = self.start_countdown {
+                    self.start_countdown = ticks.checked_sub(1).filter(|&ticks| ticks > 0);
+                    if self.start_countdown.is_none() {
+                        self.clock.resume();
+                    }
+                    orders.after_next_render(|_| Msg::DrawCanvas);
+                }
 
-        let clock = Instant::now();
+                // ラッシュモードで制限時間を過ぎたら終了。一時停止中は経過時間が進まない。
+                if self
+                    .settings
+                    .rush_limit
+                    .is_some_and(|limit| self.elapsed() >= limit)
+                {
+                    return self.time_up(orders);
+                }
 
-        Self {
-            asset,
-            board,
-            clock,
-            sq_select: None,
-            mv_last: None,
-            path_timer: 0,
-            el_canvas: Default::default(),
-        }
-    }
+                // 表示時間は実時間で判定する。バックグラウンドのタブなどでティックが
+                // 間引かれても、表示が長引くことはない。
+                let now = Instant::now();
 
-    fn restart(&mut self) {
-        self.board = Board::random(
-            NonZeroUsize::new(NCOL_INNER).unwrap(),
-            NonZeroUsize::new(NROW_INNER).unwrap(),
-        );
+                // 経路は徐々に描くので、表示中は毎ティック再描画する。
+                if let Some(since) = self.path_since {
+                    if now - since >= self.settings.path_duration() {
+                        self.path_since = None;
+                    }
+                    orders.after_next_render(|_| Msg::DrawCanvas);
+                }
 
-        self.clock = Instant::now();
+                if self
+                    .combo_last
+                    .is_some_and(|(_, at)| now - at > COMBO_WINDOW)
+                {
+                    self.combo_last = None;
+                    self.combo = 0;
+                }
 
-        self.sq_select = None;
-        self.mv_last = None;
-        self.path_timer = 0;
-    }
+                if self
+                    .error_since
+                    .is_some_and(|since| now - since >= ERROR_FLASH_DURATION)
+                {
+                    self.error_since = None;
+                    self.sqs_error = None;
+                    orders.after_next_render(|_| Msg::DrawCanvas);
+                }
 
-    fn update(mut self, msg: Msg, orders: &mut impl Orders<Msg>) -> Model {
-        match msg {
-            Msg::Restart => {
-                orders.after_next_render(|_| Msg::ModelInit);
-                // XXX: 新しい Model::Playing を返すと el_ref() が効かない問題が起こるので...
-                self.restart();
-            }
-            Msg::ModelInit => {
-                orders.after_next_render(|_| Msg::DrawCanvas);
-            }
-            Msg::Timer => {
-                if self.path_timer > 0 {
-                    self.path_timer -= 1;
-                    if self.path_timer == 0 {
-                        orders.after_next_render(|_| Msg::DrawCanvas);
+                let paused = self.is_paused();
+                if let Some(replay) = self.replay.as_mut().filter(|_| !paused) {
+                    replay.timer -= 1;
+                    if replay.timer == 0 {
+                        return self.step_replay(orders);
                     }
                 }
             }
             Msg::DrawCanvas => {
                 self.draw_canvas();
             }
+            Msg::CanvasClick(_) if self.is_paused() || self.replay.is_some() => {}
             Msg::CanvasClick(mouse) => {
-                if let Some(sq) = self.mouse_pos_to_square(mouse.offset_x(), mouse.offset_y()) {
-                    if let Some(sq_select) = self.sq_select {
-                        if let Some(mv) = self.board.shortest_move_between(sq_select, sq) {
-                            let _ = self.asset.sound_pick().play().unwrap();
-                            self.board.do_move(&mv);
-                            self.mv_last = Some(mv);
-                            self.path_timer = 30;
-
-                            /*
-                            // クリアか stuck まで進めてみるテスト
-                            while let Some(mv) = self.board.find_move() {
-                                self.board.do_move(&mv);
-                            }
-                            */
-
-                            // クリア判定。
-                            if self.board.is_empty() {
-                                orders.after_next_render(|_| Msg::ModelInit);
-                                return Model::Win(ModelWin::new(self.asset, self.clock.elapsed()));
-                            }
-
-                            // stuck 判定。
-                            if self.board.is_stuck() {
-                                orders.after_next_render(|_| Msg::ModelInit);
-                                return Model::Stuck(ModelStuck::new(
-                                    self.asset,
-                                    self.board,
-                                    self.clock.elapsed(),
-                                ));
-                            }
-                        }
-                        self.sq_select = None;
-                    } else if self.board[sq].is_tile() {
-                        self.sq_select = Some(sq);
+                let sq = self.mouse_pos_to_square(mouse.offset_x(), mouse.offset_y());
+                let now = Instant::now();
+                // 2 回目のクリックは通常の選択処理をせず、ダブルクリックとしてのみ扱う。
+                let sq_double = sq.filter(|&sq| {
+                    matches!(self.click_last, Some((sq_last, at))
+                        if sq_last == sq && now - at <= DOUBLE_CLICK_WINDOW)
+                });
+                if let Some(sq) = sq_double {
+                    self.click_last = None;
+                    return self.double_click_square(sq, orders);
+                }
+                self.click_last = sq.map(|sq| (sq, now));
+                return self.click_square(sq, orders);
+            }
+            Msg::CanvasRightClick => {
+                if self.sq_select.take().is_some() {
+                    self.update_preview();
+                    orders.after_next_render(|_| Msg::DrawCanvas);
+                }
+            }
+            Msg::CanvasMouseMove(_) if self.is_paused() || self.replay.is_some() => {}
+            Msg::CanvasMouseMove(mouse) => {
+                // mousemove のたびに再描画しないよう、ポインタ下のマスが変わったときのみ更新する。
+                let sq_hover = self.mouse_pos_to_square(mouse.offset_x(), mouse.offset_y());
+                if sq_hover != self.sq_hover {
+                    self.sq_hover = sq_hover;
+                    if self.update_preview() {
+                        orders.after_next_render(|_| Msg::DrawCanvas);
+                    }
+                }
+            }
+            Msg::CanvasMouseLeave => {
+                self.sq_hover = None;
+                if self.update_preview() {
+                    orders.after_next_render(|_| Msg::DrawCanvas);
+                }
+            }
+            Msg::CanvasTouchStart(_) | Msg::CanvasTouchMove(_) | Msg::CanvasTouchEnd(_)
+                if self.is_paused() || self.replay.is_some() => {}
+            Msg::CanvasTouchStart(touch) => {
+                // タップも含めてタッチ操作は全てここで扱うので、スクロールやマウスイベントの
+                // エミュレーションは止める。
+                touch.prevent_default();
+                let Some(sq) = self.touch_pos_to_square(&touch) else {
+                    return Model::Playing(Box::new(self));
+                };
+                // 選択中でなければ触れた牌をすぐに選択する。選択中の場合はタップかドラッグかが
+                // 決まるまで選択を変えない。
+                let selected = self.sq_select.is_none() && self.board[sq].is_tile();
+                if selected {
+                    self.clock.start_if_waiting();
+                    self.asset.play_select();
+                    self.sq_select = Some(sq);
+                    self.update_preview();
+                    orders.after_next_render(|_| Msg::DrawCanvas);
+                }
+                self.touch = Some(TouchDrag {
+                    sq_start: sq,
+                    moved: false,
+                    selected,
+                });
+            }
+            Msg::CanvasTouchMove(touch) => {
+                touch.prevent_default();
+                let sq_hover = self.touch_pos_to_square(&touch);
+                let Some(drag) = self.touch.as_mut() else {
+                    return Model::Playing(Box::new(self));
+                };
+                // 始点の牌から指が離れたらドラッグとみなし、始点の牌を選択して経路をプレビューする。
+                if sq_hover != Some(drag.sq_start) && !drag.moved {
+                    drag.moved = true;
+                    if self.board[drag.sq_start].is_tile() {
+                        self.sq_select = Some(drag.sq_start);
                     }
+                }
+                if sq_hover != self.sq_hover {
+                    self.sq_hover = sq_hover;
+                    self.update_preview();
                     orders.after_next_render(|_| Msg::DrawCanvas);
                 }
             }
-            _ => panic!("unexpected message: {msg:?}"),
+            Msg::CanvasTouchEnd(touch) => {
+                touch.prevent_default();
+                let sq = self.touch_pos_to_square(&touch);
+                self.sq_hover = None;
+                let Some(drag) = self.touch.take() else {
+                    return Model::Playing(Box::new(self));
+                };
+                // 動かさずに離した場合はタップとしてクリックと同じに扱う。
+                // ただし触れたときに選択した牌は、そのまま選択しておく。
+                if drag.moved || !drag.selected {
+                    return self.click_square(sq, orders);
+                }
+                self.update_preview();
+                orders.after_next_render(|_| Msg::DrawCanvas);
+            }
+            _ => log!("unexpected message:", msg),
         }
 
-        Model::Playing(self)
+        Model::Playing(Box::new(self))
     }
 
     fn draw_canvas(&self) {
-        let canvas = self.el_canvas.get().unwrap();
-        let ctx = canvas_context_2d(&canvas);
+        let tile_size = self.settings.tile_size();
+        let (width, height) = tile_size.canvas_size(&self.board);
+        let Some(ctx) = scaled_context_2d(&self.el_canvas, (width, height)) else {
+            return;
+        };
 
-        // 背景を描画。
-        ctx.set_fill_style(&JsValue::from("rgb(0, 128, 64)"));
-        ctx.fill_rect(
-            0.0,
-            0.0,
-            f64::from(canvas.width()),
-            f64::from(canvas.height()),
-        );
+        draw_background(&ctx, &self.settings.theme, width, height);
+
+        if self.settings.show_border {
+            draw_border(&ctx, tile_size, &self.board);
+        }
 
         // 牌を描画。
         for sq in self.board.squares_inner() {
             if let BoardCell::Tile(tile) = self.board[sq] {
                 let img = self.asset.image_tile(tile);
-                // 外周に 1px のマージンを設ける。
-                let w = f64::from(TILE_WIDTH);
-                let h = f64::from(TILE_HEIGHT);
-                let x = 1.0 + w * f64::from(u32::try_from(sq.c).unwrap());
-                let y = 1.0 + h * f64::from(u32::try_from(sq.r).unwrap());
-                ctx.draw_image_with_image_bitmap_and_dw_and_dh(img, x, y, w - 2.0, h - 2.0)
-                    .unwrap();
-
-                // 選択中の牌は強調表示。
+                let rect = tile_size.tile_rect(sq);
+                draw_tile(&ctx, img, rect, self.settings.rounded_tiles);
+                let (x, y, w, h) = rect;
+
+                if self.show_kinds {
+                    draw_tile_kind(&ctx, tile, rect);
+                }
+
+                // 選択中の牌は枠線で強調表示。枠線が牌の内側に収まるよう線幅の半分だけ内側に描く。
                 if self.sq_select.map_or(false, |sq_select| sq_select == sq) {
-                    ctx.set_fill_style(&JsValue::from("rgba(255, 255, 0, 0.3)"));
-                    ctx.fill_rect(x, y, w - 2.0, h - 2.0);
+                    let lw = 4.0;
+                    ctx.set_line_width(lw);
+                    ctx.set_stroke_style(&JsValue::from("yellow"));
+                    ctx.stroke_rect(x + lw / 2.0, y + lw / 2.0, w - lw, h - lw);
                 }
             }
         }
 
-        // 最終手の経路を描画。
-        if self.path_timer > 0 {
+        // 選択中の牌から取れる牌を光らせる。選択の変更後に求め直していなければ描かない。
+        if let Some((_, sqs)) = self
+            .sqs_target
+            .as_ref()
+            .filter(|e| Some(e.0) == self.sq_select)
+        {
+            ctx.save();
+            ctx.set_line_width(3.0);
+            ctx.set_stroke_style(&JsValue::from("rgba(255, 255, 160, 0.9)"));
+            ctx.set_shadow_color("rgba(255, 255, 0, 0.9)");
+            ctx.set_shadow_blur(10.0);
+            for &sq in sqs {
+                let (x, y, w, h) = tile_size.tile_rect(sq);
+                ctx.stroke_rect(x + 1.5, y + 1.5, w - 3.0, h - 3.0);
+            }
+            ctx.restore();
+        }
+
+        // ヒントの着手の両端の牌を枠線で示す。
+        if let Some(mv) = self.mv_hint.as_ref() {
+            ctx.set_line_width(4.0);
+            ctx.set_stroke_style(&JsValue::from("cyan"));
+            for sq in [mv.src(), mv.dst()] {
+                let (x, y, w, h) = tile_size.tile_rect(sq);
+                ctx.stroke_rect(x + 2.0, y + 2.0, w - 4.0, h - 4.0);
+            }
+        }
+
+        // 選択中の牌から取れる牌をポイントしている場合、経路のプレビューを薄く描画。
+        if let Some(mv) = self.mv_preview.as_ref() {
+            ctx.set_line_width(4.0);
+            ctx.set_line_cap("round");
+            ctx.set_stroke_style(&JsValue::from("rgba(255, 165, 0, 0.5)"));
+            Self::stroke_path(&ctx, tile_size, mv);
+        }
+
+        // 最終手の経路を、表示時間の経過に合わせて始点から終点へ徐々に描画し、
+        // 末尾の `PATH_FADE_FRACTION` の間に透明にしていく。線幅は変えない。
+        if let Some(since) = self.path_since {
+            let duration = self.settings.path_duration().as_secs_f64();
+            let t = if duration > 0.0 {
+                (since.elapsed().as_secs_f64() / duration).min(1.0)
+            } else {
+                1.0
+            };
+            let ratio = (t / (1.0 - PATH_FADE_FRACTION)).min(1.0);
+            let alpha = ((1.0 - t) / PATH_FADE_FRACTION).min(1.0);
+
+            ctx.save();
+            ctx.set_global_alpha(alpha);
             ctx.set_line_width(8.0);
             ctx.set_line_cap("round");
-            ctx.set_stroke_style(&JsValue::from("orange"));
-            ctx.begin_path();
-            let mv = self.mv_last.as_ref().expect("mv_last should be some");
-            for sqs in mv.path().windows(2) {
-                let (x1, y1) = Self::center_of_square(sqs[0]);
-                let (x2, y2) = Self::center_of_square(sqs[1]);
-                ctx.move_to(x1, y1);
-                ctx.line_to(x2, y2);
-            }
-            ctx.stroke();
+            let (mv, tile) = self.mv_last.as_ref().expect("mv_last should be some");
+            ctx.set_stroke_style(&JsValue::from(path_color(*tile)));
+            Self::stroke_path_partial(&ctx, tile_size, mv, ratio);
+            ctx.restore();
         }
-    }
 
-    fn center_of_square(sq: Square) -> (f64, f64) {
-        let c = f64::from(u32::try_from(sq.c).unwrap());
-        let r = f64::from(u32::try_from(sq.r).unwrap());
+        // 経路のない組を選んだ場合、両方の牌を赤くする。
+        if let Some((sq1, sq2)) = self.sqs_error {
+            ctx.set_fill_style(&JsValue::from("rgba(255, 0, 0, 0.4)"));
+            for sq in [sq1, sq2] {
+                let (x, y, w, h) = tile_size.tile_rect(sq);
+                ctx.fill_rect(x, y, w, h);
+            }
+        }
 
-        let w = f64::from(TILE_WIDTH);
-        let h = f64::from(TILE_HEIGHT);
+        if self.show_grid {
+            self.draw_debug_grid(&ctx, tile_size);
+        }
 
-        let x = w * c + w / 2.0;
-        let y = h * r + h / 2.0;
+        // 一時停止中は全体を暗くする。
+        if self.is_paused() {
+            ctx.set_fill_style(&JsValue::from("rgba(0, 0, 0, 0.5)"));
+            ctx.fill_rect(0.0, 0.0, f64::from(width), f64::from(height));
+        }
 
-        (x, y)
+        // カウントダウン中は残り秒数を中央に大きく描く。
+        if let Some(label) = self.countdown_label() {
+            let size = 2.0 * f64::from(tile_size.height.min(tile_size.width));
+            ctx.set_font(&format!("bold {size}px sans-serif"));
+            ctx.set_text_align("center");
+            ctx.set_text_baseline("middle");
+            ctx.set_fill_style(&JsValue::from("white"));
+            ctx.fill_text(&label, f64::from(width) / 2.0, f64::from(height) / 2.0)
+                .unwrap();
+            ctx.set_text_align("start");
+        }
     }
 
-    fn mouse_pos_to_square(&self, x: i32, y: i32) -> Option<Square> {
-        // x または y が負なら None を返す。
-        let x = match u32::try_from(x) {
-            Ok(x) => x,
-            Err(_) => return None,
-        };
-        let y = match u32::try_from(y) {
-            Ok(y) => y,
-            Err(_) => return None,
-        };
+    /// カウントダウン中に表示する文字列を返す。カウントダウン中でなければ None を返す。
+    fn countdown_label(&self) -> Option<String> {
+        let ms = self.start_countdown? * TICK_MS;
 
-        let ncol = self.board.ncol().get();
-        let nrow = self.board.nrow().get();
+        if ms <= COUNTDOWN_GO_MS {
+            Some(self.settings.lang().tr(Text::Go).to_owned())
+        } else {
+            Some((ms - COUNTDOWN_GO_MS).div_ceil(1000).to_string())
+        }
+    }
 
-        let c = usize::try_from(x / TILE_WIDTH).unwrap();
-        let r = usize::try_from(y / TILE_HEIGHT).unwrap();
+    /// 外周を含む全マスの境界線を描き、上端と左端のマスに列番号と行番号を描く。
+    fn draw_debug_grid(&self, ctx: &CanvasRenderingContext2d, tile_size: TileSize) {
+        let w = f64::from(tile_size.width);
+        let h = f64::from(tile_size.height);
+
+        ctx.set_line_width(1.0);
+        ctx.set_stroke_style(&JsValue::from("rgba(255, 255, 255, 0.5)"));
+        ctx.set_font("10px monospace");
+        ctx.set_text_baseline("top");
+        ctx.set_fill_style(&JsValue::from("white"));
+
+        for sq in self.board.squares() {
+            let (x, y) = tile_size.square_origin(sq);
+            let (x, y) = (f64::from(x), f64::from(y));
+            ctx.stroke_rect(x + 0.5, y + 0.5, w - 1.0, h - 1.0);
+
+            if sq.r == 0 {
+                ctx.fill_text(&sq.c.to_string(), x + 2.0, y + 2.0).unwrap();
+            } else if sq.c == 0 {
+                ctx.fill_text(&sq.r.to_string(), x + 2.0, y + 2.0).unwrap();
+            }
+        }
+    }
 
-        if c >= ncol || r >= nrow {
-            return None;
+    /// 着手の経路を現在のスタイルで描画する。
+    fn stroke_path(ctx: &CanvasRenderingContext2d, tile_size: TileSize, mv: &Move) {
+        ctx.begin_path();
+        for sqs in mv.path().windows(2) {
+            let (x1, y1) = tile_size.square_center(sqs[0]);
+            let (x2, y2) = tile_size.square_center(sqs[1]);
+            ctx.move_to(x1, y1);
+            ctx.line_to(x2, y2);
         }
+        ctx.stroke();
+    }
 
-        Some(Square::new(c, r))
+    /// 着手の経路のうち、始点から全長の `ratio` (0.0..=1.0) 倍までを現在のスタイルで描画する。
+    fn stroke_path_partial(
+        ctx: &CanvasRenderingContext2d,
+        tile_size: TileSize,
+        mv: &Move,
+        ratio: f64,
+    ) {
+        let segments: Vec<_> = mv
+            .path()
+            .windows(2)
+            .map(|sqs| {
+                let (x1, y1) = tile_size.square_center(sqs[0]);
+                let (x2, y2) = tile_size.square_center(sqs[1]);
+                (x1, y1, x2, y2, (x2 - x1).hypot(y2 - y1))
+            })
+            .collect();
+
+        let mut rest = ratio * segments.iter().map(|seg| seg.4).sum::<f64>();
+
+        ctx.begin_path();
+        for (x1, y1, x2, y2, len) in segments {
+            if rest <= 0.0 {
+                break;
+            }
+            let t = (rest / len).min(1.0);
+            ctx.move_to(x1, y1);
+            ctx.line_to(x1 + (x2 - x1) * t, y1 + (y2 - y1) * t);
+            rest -= len;
+        }
+        ctx.stroke();
+    }
+
+    fn mouse_pos_to_square(&self, x: i32, y: i32) -> Option<Square> {
+        self.settings
+            .tile_size()
+            .square_at(x, y, self.board.ncol().get(), self.board.nrow().get())
+    }
+
+    /// タッチイベントで変化したタッチ点の下にあるマスを返す。
+    fn touch_pos_to_square(&self, touch: &TouchEvent) -> Option<Square> {
+        let canvas = self.el_canvas.get()?;
+        let touch = touch.changed_touches().get(0)?;
+        let size = self.settings.tile_size().canvas_size(&self.board);
+        let (x, y) = touch_pos_on_canvas(&canvas, size, &touch);
+
+        self.mouse_pos_to_square(x, y)
     }
 
     fn view(&self) -> Node<Msg> {
-        div![self.view_canvas(), self.view_ui()]
+        div![
+            self.view_canvas(),
+            self.view_ui(),
+            IF!(self.show_settings => self.view_settings_panel()),
+        ]
     }
 
     fn view_canvas(&self) -> Node<Msg> {
+        // 右クリックで選択を解除する。選択中でなければブラウザのメニューをそのまま出す。
+        let selecting = self.sq_select.is_some();
+        let (width, height) = self.settings.tile_size().canvas_size(&self.board);
+        let (backing_width, backing_height) = canvas_backing_size((width, height));
+
         div![canvas![
             el_ref(&self.el_canvas),
             el_key(&"playing_canvas"),
             attrs! {
-                At::Width => px(CANVAS_WIDTH),
-                At::Height => px(CANVAS_HEIGHT),
+                At::Width => backing_width,
+                At::Height => backing_height,
+            },
+            style! {
+                St::Width => px(width),
+                St::Height => px(height),
             },
             mouse_ev(Ev::Click, Msg::CanvasClick),
+            mouse_ev(Ev::ContextMenu, move |mouse| {
+                selecting.then(|| {
+                    mouse.prevent_default();
+                    Msg::CanvasRightClick
+                })
+            }),
+            mouse_ev(Ev::MouseMove, Msg::CanvasMouseMove),
+            ev(Ev::MouseLeave, |_| Msg::CanvasMouseLeave),
+            touch_ev(Ev::TouchStart, Msg::CanvasTouchStart),
+            touch_ev(Ev::TouchMove, Msg::CanvasTouchMove),
+            touch_ev(Ev::TouchEnd, Msg::CanvasTouchEnd),
         ]]
     }
 
     fn view_ui(&self) -> Node<Msg> {
+        let cleared = self.tiles_initial - self.board.tile_count();
+        let lang = self.settings.lang();
+
         div![
             C!["ui"],
-            div![span![util::format_duration(self.clock.elapsed())]],
-            div![button!["Restart", ev(Ev::Click, |_| Msg::Restart)]],
+            view_progress_bar(cleared as f64 / self.tiles_initial.max(1) as f64),
+            div![
+                span![match self.settings.rush_limit {
+                    Some(limit) => format!(
+                        "{}: {}",
+                        lang.tr(Text::TimeLeft),
+                        util::format_duration(limit.saturating_sub(self.elapsed()))
+                    ),
+                    None => util::format_duration(self.elapsed()),
+                }],
+                " ",
+                span![format!(
+                    "{tiles}: {tile_count} ({pair_count} {pairs})",
+                    tiles = lang.tr(Text::Tiles),
+                    tile_count = self.board.tile_count(),
+                    pair_count = self.board.tile_count() / 2,
+                    pairs = lang.tr(Text::Pairs),
+                )],
+                IF!(self.settings.endless => span![format!(
+                    " {}: {}",
+                    lang.tr(Text::Cleared),
+                    self.tiles_cleared
+                )]),
+                IF!(self.combo >= 2 => span![
+                    " ",
+                    strong![format!("{} x{}", lang.tr(Text::Combo), self.combo)]
+                ]),
+            ],
+            IF!(self.dead_end => div![
+                C!["banner"],
+                lang.tr(Text::NoSolutionRemains),
+                button![lang.tr(Text::Shuffle), ev(Ev::Click, |_| Msg::Shuffle)],
+            ]),
+            div![
+                button![lang.tr(Text::Restart), ev(Ev::Click, |_| Msg::Restart)],
+                " ",
+                button![lang.tr(Text::Retry), ev(Ev::Click, |_| Msg::ReplayBoard)],
+                " ",
+                self.view_pause_button(),
+            ],
+            div![
+                button![
+                    format!("{} ({})", lang.tr(Text::Hint), self.hints_left),
+                    attrs! { At::Disabled => (self.hints_left == 0).as_at_value() },
+                    ev(Ev::Click, |_| Msg::Hint),
+                ],
+                " ",
+                button![
+                    format!("{} ({})", lang.tr(Text::SmartHint), self.hints_left),
+                    attrs! { At::Disabled => (self.hints_left == 0).as_at_value() },
+                    ev(Ev::Click, |_| Msg::SmartHint),
+                ],
+                " ",
+                button![
+                    lang.tr(Text::Undo),
+                    attrs! { At::Disabled => self.stats_history.is_empty().as_at_value() },
+                    ev(Ev::Click, |_| Msg::Undo),
+                ],
+            ],
+            div![
+                button![
+                    lang.tr(Text::CheckSolvable),
+                    ev(Ev::Click, |_| Msg::CheckSolvable)
+                ],
+                match self.solvable {
+                    Some(true) => span![" ", lang.tr(Text::Solvable)],
+                    Some(false) => span![" ", lang.tr(Text::NotSolvable)],
+                    None => empty![],
+                },
+                " ",
+                button![
+                    lang.tr(Text::AutoSolve),
+                    attrs! { At::Disabled => self.replay.is_some().as_at_value() },
+                    ev(Ev::Click, |_| Msg::AutoSolve),
+                ],
+            ],
+            div![button![
+                format!("⚙ {}", lang.tr(Text::Settings)),
+                ev(Ev::Click, |_| Msg::SettingsToggle)
+            ]],
+            div![label![
+                input![
+                    attrs! {
+                        At::Type => "checkbox",
+                        At::Checked => self.show_legend.as_at_value(),
+                    },
+                    ev(Ev::Change, |_| Msg::KindLegendToggle),
+                ],
+                lang.tr(Text::ShowRemainingByKind),
+            ]],
+            IF!(self.show_legend => self.view_kind_legend()),
+            self.view_history(),
+            IF!(self.saved.is_some() => div![
+                button![
+                    lang.tr(Text::ResumeSavedGame),
+                    ev(Ev::Click, |_| Msg::ResumeSaved)
+                ],
+            ]),
+            div![small![lang.tr(Text::Shortcuts)]],
+            self.replay.as_ref().map(|replay| div![if replay.auto {
+                lang.tr(Text::AutoSolving)
+            } else {
+                lang.tr(Text::Replaying)
+            }]),
+            self.load_error.as_ref().map(|e| div![e.as_str()]),
+        ]
+    }
+
+    /// 設定パネル。ゲームの上に重ねて表示する。変更はすぐに保存される。
+    fn view_settings_panel(&self) -> Node<Msg> {
+        let lang = self.settings.lang();
+
+        div![
+            C!["settings-panel"],
+            div![
+                self.view_difficulty_select(),
+                " ",
+                self.view_rush_select(),
+                " ",
+                self.view_gravity_select(),
+                " ",
+                self.view_symmetry_select(),
+                " ",
+                self.view_theme_select(),
+            ],
+            div![label![
+                input![
+                    attrs! {
+                        At::Type => "checkbox",
+                        At::Checked => self.settings.endless.as_at_value(),
+                    },
+                    ev(Ev::Change, |_| Msg::EndlessToggle),
+                ],
+                lang.tr(Text::Endless),
+            ]],
+            div![label![
+                input![
+                    attrs! {
+                        At::Type => "checkbox",
+                        At::Checked => self.settings.rounded_tiles.as_at_value(),
+                    },
+                    ev(Ev::Change, |_| Msg::RoundedTilesToggle),
+                ],
+                lang.tr(Text::RoundedTiles),
+            ]],
+            div![label![
+                input![
+                    attrs! {
+                        At::Type => "checkbox",
+                        At::Checked => self.settings.show_border.as_at_value(),
+                    },
+                    ev(Ev::Change, |_| Msg::BorderToggle),
+                ],
+                lang.tr(Text::ShowOuterBorder),
+            ]],
+            div![label![
+                input![
+                    attrs! {
+                        At::Type => "checkbox",
+                        At::Checked => self.show_kinds.as_at_value(),
+                    },
+                    ev(Ev::Change, |_| Msg::ShowKindsToggle),
+                ],
+                lang.tr(Text::ShowTileNumbers),
+            ]],
+            div![label![
+                input![
+                    attrs! {
+                        At::Type => "checkbox",
+                        At::Checked => self.show_grid.as_at_value(),
+                    },
+                    ev(Ev::Change, |_| Msg::DebugGridToggle),
+                ],
+                lang.tr(Text::ShowGrid),
+            ]],
+            self.view_sound_controls(),
+            self.view_path_duration_slider(),
+            div![label![
+                input![
+                    attrs! {
+                        At::Type => "checkbox",
+                        At::Checked => self.settings.warn_dead_end.as_at_value(),
+                    },
+                    ev(Ev::Change, |_| Msg::WarnDeadEndToggle),
+                ],
+                lang.tr(Text::WarnDeadEnd),
+            ]],
+            div![label![
+                input![
+                    attrs! {
+                        At::Type => "checkbox",
+                        At::Checked => self.settings.continuous_match.as_at_value(),
+                    },
+                    ev(Ev::Change, |_| Msg::ContinuousMatchToggle),
+                ],
+                lang.tr(Text::ContinuousMatch),
+            ]],
+            div![label![
+                input![
+                    attrs! {
+                        At::Type => "checkbox",
+                        At::Checked => self.settings.show_targets.as_at_value(),
+                    },
+                    ev(Ev::Change, |_| Msg::ShowTargetsToggle),
+                ],
+                lang.tr(Text::ShowTargets),
+            ]],
+            div![label![
+                input![
+                    attrs! {
+                        At::Type => "checkbox",
+                        At::Checked => self.settings.countdown.as_at_value(),
+                    },
+                    ev(Ev::Change, |_| Msg::CountdownToggle),
+                ],
+                lang.tr(Text::Countdown),
+            ]],
+            div![label![
+                input![
+                    attrs! {
+                        At::Type => "checkbox",
+                        At::Checked => self.settings.clock_on_first_move.as_at_value(),
+                    },
+                    ev(Ev::Change, |_| Msg::ClockOnFirstMoveToggle),
+                ],
+                lang.tr(Text::ClockOnFirstMove),
+            ]],
+            div![self.view_motion_select()],
+            div![self.view_tile_aspect_select()],
+            div![self.view_lang_select()],
+            div![button![
+                lang.tr(Text::Close),
+                ev(Ev::Click, |_| Msg::SettingsToggle)
+            ]],
+        ]
+    }
+
+    fn view_motion_select(&self) -> Node<Msg> {
+        let current = match self.settings.reduced_motion_override {
+            None => "system",
+            Some(true) => "reduce",
+            Some(false) => "full",
+        };
+
+        let lang = self.settings.lang();
+
+        label![
+            format!("{}: ", lang.tr(Text::Animations)),
+            select![
+                [
+                    ("system", lang.tr(Text::SystemDefault)),
+                    ("reduce", lang.tr(Text::ReducedMotion)),
+                    ("full", lang.tr(Text::FullMotion)),
+                ]
+                .iter()
+                .map(|&(value, label)| {
+                    option![
+                        attrs! {
+                            At::Value => value,
+                            At::Selected => (value == current).as_at_value(),
+                        },
+                        label,
+                    ]
+                }),
+                input_ev(Ev::Change, Msg::ReducedMotionChange),
+            ],
+        ]
+    }
+
+    /// UI の言語の選択欄。"" はブラウザの設定に従う。
+    fn view_lang_select(&self) -> Node<Msg> {
+        let current = self.settings.lang_override;
+
+        label![
+            format!("{}: ", self.settings.lang().tr(Text::Language)),
+            select![
+                option![
+                    attrs! {
+                        At::Value => "",
+                        At::Selected => current.is_none().as_at_value(),
+                    },
+                    self.settings.lang().tr(Text::BrowserDefault),
+                ],
+                LANGS.iter().map(|&(lang, value, label)| {
+                    option![
+                        attrs! {
+                            At::Value => value,
+                            At::Selected => (Some(lang) == current).as_at_value(),
+                        },
+                        label,
+                    ]
+                }),
+                input_ev(Ev::Change, Msg::LangChange),
+            ],
+        ]
+    }
+
+    fn view_tile_aspect_select(&self) -> Node<Msg> {
+        select![
+            TILE_ASPECTS.iter().map(|&(tile_aspect, value, label)| {
+                option![
+                    attrs! {
+                        At::Value => value,
+                        At::Selected => (tile_aspect == self.settings.tile_aspect).as_at_value(),
+                    },
+                    self.settings.lang().tr(label),
+                ]
+            }),
+            input_ev(Ev::Change, Msg::TileAspectChange),
+        ]
+    }
+
+    /// これまでの着手の一覧。新しい着手が上に来る。クリックするとその経路を表示する。
+    fn view_history(&self) -> Node<Msg> {
+        let format_square = |sq: Square| format!("({}, {})", sq.c, sq.r);
+        let kind_label = self.settings.lang().tr(Text::Kind);
+
+        ol![
+            C!["history"],
+            attrs! { At::Reversed => AtValue::None },
+            self.moves
+                .iter()
+                .zip(&self.move_kinds)
+                .enumerate()
+                .rev()
+                .map(|(i, (mv, kind))| {
+                    li![
+                        format!(
+                            "{} → {} ({kind_label} {kind})",
+                            format_square(mv.src()),
+                            format_square(mv.dst()),
+                        ),
+                        ev(Ev::Click, move |_| Msg::HistoryFlash(i)),
+                    ]
+                }),
+        ]
+    }
+
+    /// 種類ごとの残り枚数を、残っている種類のみ `番号: 枚数` の形で並べる。
+    fn view_kind_legend(&self) -> Node<Msg> {
+        let items = self
+            .board
+            .kind_counts()
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, count)| count > 0)
+            .map(|(kind, count)| format!("{kind}: {count}"))
+            .join(", ");
+
+        div![small![items]]
+    }
+
+    fn view_pause_button(&self) -> Node<Msg> {
+        if self.clock.is_paused() {
+            button![
+                self.settings.lang().tr(Text::Resume),
+                attrs! { At::Disabled => self.start_countdown.is_some().as_at_value() },
+                ev(Ev::Click, |_| Msg::Resume)
+            ]
+        } else {
+            button![
+                self.settings.lang().tr(Text::Pause),
+                attrs! { At::Disabled => self.clock.is_waiting().as_at_value() },
+                ev(Ev::Click, |_| Msg::Pause)
+            ]
+        }
+    }
+
+    fn view_symmetry_select(&self) -> Node<Msg> {
+        select![
+            SYMMETRIES.iter().map(|&(symmetry, value, label)| {
+                option![
+                    attrs! {
+                        At::Value => value,
+                        At::Selected => (symmetry == self.settings.symmetry).as_at_value(),
+                    },
+                    self.settings.lang().tr(label),
+                ]
+            }),
+            input_ev(Ev::Change, Msg::SymmetryChange),
+        ]
+    }
+
+    fn view_gravity_select(&self) -> Node<Msg> {
+        select![
+            GRAVITY_MODES.iter().map(|&(gravity, value, label)| {
+                option![
+                    attrs! {
+                        At::Value => value,
+                        At::Selected => (gravity == self.settings.gravity).as_at_value(),
+                    },
+                    self.settings.lang().tr(label),
+                ]
+            }),
+            input_ev(Ev::Change, Msg::GravityChange),
+        ]
+    }
+
+    /// 難易度の選択欄。設定がどの難易度にも一致しない場合は "Custom" を表示する。
+    fn view_difficulty_select(&self) -> Node<Msg> {
+        let current = self.settings.difficulty();
+
+        select![
+            IF!(current.is_none() => option![
+                attrs! {
+                    At::Value => "",
+                    At::Selected => AtValue::None,
+                    At::Disabled => AtValue::None,
+                },
+                self.settings.lang().tr(Text::Custom),
+            ]),
+            DIFFICULTIES.iter().map(|&(difficulty, value, label)| {
+                option![
+                    attrs! {
+                        At::Value => value,
+                        At::Selected => (Some(difficulty) == current).as_at_value(),
+                    },
+                    self.settings.lang().tr(label),
+                ]
+            }),
+            input_ev(Ev::Change, Msg::DifficultyChange),
+        ]
+    }
+
+    fn view_rush_select(&self) -> Node<Msg> {
+        let current = self.settings.rush_limit.map_or(0, |limit| limit.as_secs());
+
+        select![
+            RUSH_LIMITS.iter().map(|&(secs, label)| {
+                option![
+                    attrs! {
+                        At::Value => secs,
+                        At::Selected => (secs == current).as_at_value(),
+                    },
+                    self.settings.lang().tr(label),
+                ]
+            }),
+            input_ev(Ev::Change, Msg::RushChange),
+        ]
+    }
+
+    fn view_sound_controls(&self) -> Node<Msg> {
+        div![
+            label![
+                input![
+                    attrs! {
+                        At::Type => "checkbox",
+                        At::Checked => self.asset.is_muted().as_at_value(),
+                    },
+                    ev(Ev::Change, |_| Msg::MuteToggle),
+                ],
+                self.settings.lang().tr(Text::Mute),
+            ],
+            " ",
+            input![
+                attrs! {
+                    At::Type => "range",
+                    At::Min => 0,
+                    At::Max => 1,
+                    At::Step => 0.05,
+                    At::Value => self.asset.volume(),
+                    At::Disabled => self.asset.is_muted().as_at_value(),
+                },
+                input_ev(Ev::Input, Msg::VolumeChange),
+            ],
+        ]
+    }
+
+    fn view_path_duration_slider(&self) -> Node<Msg> {
+        div![
+            format!("{}: ", self.settings.lang().tr(Text::PathDisplay)),
+            input![
+                attrs! {
+                    At::Type => "range",
+                    At::Min => 0,
+                    At::Max => PATH_DURATION_MS_MAX,
+                    At::Step => 100,
+                    At::Value => self.settings.path_duration_ms,
+                },
+                input_ev(Ev::Input, Msg::PathDurationChange),
+            ],
+            format!(" {} ms", self.settings.path_duration_ms),
+        ]
+    }
+
+    fn view_theme_select(&self) -> Node<Msg> {
+        select![
+            asset::THEMES.iter().map(|&(theme, label)| {
+                option![
+                    attrs! {
+                        At::Value => theme,
+                        At::Selected => (theme == self.settings.theme).as_at_value(),
+                    },
+                    self.settings.lang().tr(label),
+                ]
+            }),
+            input_ev(Ev::Change, Msg::ThemeChange),
         ]
     }
 }
@@ -345,24 +3063,104 @@ impl ModelPlaying {
 #[derive(Debug)]
 struct ModelWin {
     asset: Asset,
+    settings: Settings,
+    /// 開始時の盤面。同じ盤面で遊び直す場合に使う。
+    board_initial: Board,
+    /// 最後の組を取る前の盤面。背景に薄く表示する。
+    board_last: Board,
     elapsed: Duration,
+    moves: Vec<Move>,
+    /// 使ったヒントの数。
+    hints_used: u32,
+    /// スコアの計算に使う集計値。
+    stats: PlayStats,
+    /// 開始時の盤面の牌の組の数。
+    total_pairs: usize,
     el_canvas: ElRef<HtmlCanvasElement>,
 }
 
 impl ModelWin {
-    fn new(asset: Asset, elapsed: Duration) -> Self {
+    fn new(
+        asset: Asset,
+        settings: Settings,
+        board_initial: Board,
+        elapsed: Duration,
+        moves: Vec<Move>,
+        hints_used: u32,
+        stats: PlayStats,
+    ) -> Self {
+        let total_pairs = board_initial.tile_count() / 2;
+
+        // 開始時の盤面に最後以外の着手を適用し、最後の組を取る前の盤面を得る。
+        let mut board_last = board_initial.clone();
+        for mv in moves.iter().take(moves.len().saturating_sub(1)) {
+            board_last.do_move(mv);
+        }
+
         Self {
             asset,
+            settings,
+            board_initial,
+            board_last,
             elapsed,
+            moves,
+            hints_used,
+            stats,
+            total_pairs,
             el_canvas: Default::default(),
         }
     }
 
+    /// クリアまでの経過時間を返す。ヒントのペナルティは含まない。
+    fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// ヒントのペナルティを加えたクリアタイムを返す。
+    fn penalized_elapsed(&self) -> Duration {
+        self.elapsed() + HINT_PENALTY * self.hints_used
+    }
+
+    /// スコアを返す。経過時間にはヒントのペナルティを含める。
+    fn score(&self) -> Score {
+        Score::compute(
+            self.penalized_elapsed(),
+            self.moves.len(),
+            self.total_pairs,
+            self.stats,
+        )
+    }
+
+    /// リプレイ(着手の文字列表現を空白区切りで並べたもの)を返す。
+    fn replay(&self) -> String {
+        self.moves.iter().map(Move::to_string_repr).join(" ")
+    }
+
+    /// 開始時の盤面とリプレイを含む URL を返す。
+    ///
+    /// 盤面は開始時に URL のクエリに設定されているので、それにリプレイを加える。
+    fn replay_url(&self) -> String {
+        let mut url = Url::current();
+        url.search_mut().push_value("replay", self.replay());
+
+        let origin = util::window().location().origin().unwrap_or_default();
+
+        format!("{origin}{url}")
+    }
+
     fn update(self, msg: Msg, orders: &mut impl Orders<Msg>) -> Model {
         match msg {
             Msg::Restart => {
                 orders.after_next_render(|_| Msg::ModelInit);
-                return Model::Playing(ModelPlaying::new(self.asset));
+                return Model::Playing(Box::new(ModelPlaying::new(self.asset, self.settings)));
+            }
+            Msg::ReplayBoard => {
+                orders.after_next_render(|_| Msg::ModelInit);
+                return Model::Playing(Box::new(ModelPlaying::with_board(
+                    self.asset,
+                    self.settings,
+                    self.board_initial,
+                )));
             }
             Msg::ModelInit => {
                 orders.after_next_render(|_| Msg::DrawCanvas);
@@ -370,25 +3168,83 @@ impl ModelWin {
             Msg::DrawCanvas => {
                 self.draw_canvas();
             }
-            Msg::Timer => {}
-            _ => panic!("unexpected message: {msg:?}"),
+            Msg::CopyReplay => {
+                util::copy_to_clipboard(&self.replay_url());
+            }
+            Msg::Timer | Msg::WindowBlur | Msg::WindowFocus => {}
+            _ => log!("unexpected message:", msg),
         }
 
         Model::Win(self)
     }
 
     fn draw_canvas(&self) {
-        let canvas = self.el_canvas.get().unwrap();
-        let ctx = canvas_context_2d(&canvas);
+        let tile_size = self.settings.tile_size();
+        let (width, height) = tile_size.canvas_size(&self.board_last);
+        let Some(ctx) = scaled_context_2d(&self.el_canvas, (width, height)) else {
+            return;
+        };
 
-        // 背景を描画。
-        ctx.set_fill_style(&JsValue::from("rgb(0, 128, 64)"));
-        ctx.fill_rect(
-            0.0,
-            0.0,
-            f64::from(canvas.width()),
-            f64::from(canvas.height()),
+        draw_background(&ctx, &self.settings.theme, width, height);
+
+        // 最後の組を取る前の盤面と最終手の経路を薄く描画。
+        ctx.set_global_alpha(0.3);
+        draw_board_tiles(
+            &ctx,
+            &self.asset,
+            tile_size,
+            &self.board_last,
+            self.settings.rounded_tiles,
         );
+        if let Some(mv) = self.moves.last() {
+            ctx.set_line_width(8.0);
+            ctx.set_line_cap("round");
+            ctx.set_stroke_style(&JsValue::from("orange"));
+            ModelPlaying::stroke_path(&ctx, tile_size, mv);
+        }
+        ctx.set_global_alpha(1.0);
+
+        self.draw_thumbnail(&ctx, (width, height));
+
+        // プレイ中の画面と区別できるよう、中央に文字を描く。
+        let size = f64::from(tile_size.height.min(tile_size.width));
+        ctx.set_font(&format!("bold {size}px sans-serif"));
+        ctx.set_text_align("center");
+        ctx.set_text_baseline("middle");
+        ctx.set_fill_style(&JsValue::from("gold"));
+        let text = self.settings.lang().tr(Text::Clear);
+        ctx.fill_text(text, f64::from(width) / 2.0, f64::from(height) / 2.0)
+            .unwrap();
+        ctx.set_text_align("start");
+    }
+
+    /// 開始時の盤面を縮小し、キャンバス (大きさ `canvas_size`) の右下に描画する。
+    ///
+    /// 縮小した牌は角丸にすると潰れるので、設定に関わらず角丸にしない。
+    fn draw_thumbnail(&self, ctx: &CanvasRenderingContext2d, canvas_size: (u32, u32)) {
+        let tile_size = self.settings.tile_size();
+        let thumb_tile_size = tile_size.scaled_down(THUMBNAIL_SCALE);
+        let (width, height) = thumb_tile_size.canvas_size(&self.board_initial);
+
+        // キャンバスの端に接しないよう、右下の角から半マス分離す。
+        let x = f64::from(canvas_size.0.saturating_sub(width + tile_size.width / 2));
+        let y = f64::from(canvas_size.1.saturating_sub(height + tile_size.height / 2));
+
+        ctx.save();
+        ctx.translate(x, y).unwrap();
+        ctx.set_fill_style(&JsValue::from("rgba(0, 0, 0, 0.5)"));
+        ctx.fill_rect(0.0, 0.0, f64::from(width), f64::from(height));
+        draw_board_tiles(
+            ctx,
+            &self.asset,
+            thumb_tile_size,
+            &self.board_initial,
+            false,
+        );
+        ctx.set_stroke_style(&JsValue::from("white"));
+        ctx.set_line_width(1.0);
+        ctx.stroke_rect(0.0, 0.0, f64::from(width), f64::from(height));
+        ctx.restore();
     }
 
     fn view(&self) -> Node<Msg> {
@@ -396,22 +3252,71 @@ impl ModelWin {
     }
 
     fn view_canvas(&self) -> Node<Msg> {
+        let (width, height) = self.settings.tile_size().canvas_size(&self.board_last);
+        let (backing_width, backing_height) = canvas_backing_size((width, height));
+
         div![canvas![
             el_ref(&self.el_canvas),
             el_key(&"win_canvas"),
             attrs! {
-                At::Width => px(CANVAS_WIDTH),
-                At::Height => px(CANVAS_HEIGHT),
+                At::Width => backing_width,
+                At::Height => backing_height,
+            },
+            style! {
+                St::Width => px(width),
+                St::Height => px(height),
             },
         ]]
     }
 
     fn view_ui(&self) -> Node<Msg> {
+        let score = self.score();
+        let lang = self.settings.lang();
+
         div![
             C!["ui"],
-            div![strong![util::format_duration(self.elapsed)]],
-            div!["CLEAR!"],
-            div![button!["Restart", ev(Ev::Click, |_| Msg::Restart)]],
+            view_progress_bar(1.0),
+            div![strong![util::format_duration_precise(
+                self.penalized_elapsed()
+            )]],
+            IF!(self.hints_used > 0 => div![format!(
+                "{} + {} {} x {}s",
+                util::format_duration_precise(self.elapsed()),
+                self.hints_used,
+                lang.tr(Text::Hints),
+                HINT_PENALTY.as_secs(),
+            )]),
+            div![lang.tr(Text::Clear)],
+            div![strong![format!(
+                "{}: {}",
+                lang.tr(Text::Score),
+                score.total()
+            )]],
+            div![format!(
+                "{} - {} ({}) - {} ({}) + {} ({}) + {} ({})",
+                score.base,
+                score.time_penalty,
+                lang.tr(Text::ScoreTime),
+                score.move_penalty,
+                lang.tr(Text::ScoreMoves),
+                score.path_bonus,
+                lang.tr(Text::ScoreShortestPaths),
+                score.combo_bonus,
+                lang.tr(Text::ScoreCombos),
+            )],
+            div![
+                button![lang.tr(Text::Restart), ev(Ev::Click, |_| Msg::Restart)],
+                " ",
+                button![
+                    lang.tr(Text::ReplayThisBoard),
+                    ev(Ev::Click, |_| Msg::ReplayBoard)
+                ],
+                " ",
+                button![
+                    lang.tr(Text::CopyReplay),
+                    ev(Ev::Click, |_| Msg::CopyReplay)
+                ],
+            ],
         ]
     }
 }
@@ -419,26 +3324,69 @@ impl ModelWin {
 #[derive(Debug)]
 struct ModelStuck {
     asset: Asset,
+    settings: Settings,
     board: Board,
+    /// 開始時の盤面。同じ盤面で遊び直す場合に使う。
+    board_initial: Board,
     elapsed: Duration,
+    /// 牌の種類の番号を表示するかどうか。
+    show_kinds: bool,
+    /// ラッシュモードの制限時間切れで終了したかどうか。
+    time_up: bool,
+    /// 互いに取れる牌同士だが経路がない組。手詰まりの理由として表示する。
+    blocked_pairs: Vec<(Square, Square)>,
     el_canvas: ElRef<HtmlCanvasElement>,
 }
 
 impl ModelStuck {
-    fn new(asset: Asset, board: Board, elapsed: Duration) -> Self {
+    fn new(
+        asset: Asset,
+        settings: Settings,
+        board: Board,
+        board_initial: Board,
+        elapsed: Duration,
+        show_kinds: bool,
+        time_up: bool,
+    ) -> Self {
+        // 時間切れの場合は取れる組が残っているので、手詰まりの理由は示さない。
+        let blocked_pairs = if time_up {
+            Vec::new()
+        } else {
+            board.blocked_pairs()
+        };
+
         Self {
             asset,
+            settings,
             board,
+            board_initial,
             elapsed,
+            show_kinds,
+            time_up,
+            blocked_pairs,
             el_canvas: Default::default(),
         }
     }
 
+    /// 終了(手詰まりまたは時間切れ)までの経過時間を返す。
+    fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
     fn update(self, msg: Msg, orders: &mut impl Orders<Msg>) -> Model {
         match msg {
             Msg::Restart => {
                 orders.after_next_render(|_| Msg::ModelInit);
-                return Model::Playing(ModelPlaying::new(self.asset));
+                let mut model = ModelPlaying::new(self.asset, self.settings);
+                model.show_kinds = self.show_kinds;
+                return Model::Playing(Box::new(model));
+            }
+            Msg::ReplayBoard => {
+                orders.after_next_render(|_| Msg::ModelInit);
+                let mut model =
+                    ModelPlaying::with_board(self.asset, self.settings, self.board_initial);
+                model.show_kinds = self.show_kinds;
+                return Model::Playing(Box::new(model));
             }
             Msg::ModelInit => {
                 orders.after_next_render(|_| Msg::DrawCanvas);
@@ -446,48 +3394,57 @@ impl ModelStuck {
             Msg::DrawCanvas => {
                 self.draw_canvas();
             }
-            Msg::Timer => {}
-            _ => panic!("unexpected message: {msg:?}"),
+            Msg::Timer | Msg::WindowBlur | Msg::WindowFocus => {}
+            _ => log!("unexpected message:", msg),
         }
 
         Model::Stuck(self)
     }
 
     fn draw_canvas(&self) {
-        let canvas = self.el_canvas.get().unwrap();
-        let ctx = canvas_context_2d(&canvas);
+        let tile_size = self.settings.tile_size();
+        let (width, height) = tile_size.canvas_size(&self.board);
+        let Some(ctx) = scaled_context_2d(&self.el_canvas, (width, height)) else {
+            return;
+        };
 
-        // 背景を描画。
-        ctx.set_fill_style(&JsValue::from("rgb(0, 128, 64)"));
-        ctx.fill_rect(
-            0.0,
-            0.0,
-            f64::from(canvas.width()),
-            f64::from(canvas.height()),
-        );
+        draw_background(&ctx, &self.settings.theme, width, height);
+
+        if self.settings.show_border {
+            draw_border(&ctx, tile_size, &self.board);
+        }
 
         // 牌を描画。
         for sq in self.board.squares_inner() {
             if let BoardCell::Tile(tile) = self.board[sq] {
                 let img = self.asset.image_tile(tile);
-                // 外周に 1px のマージンを設ける。
-                let w = f64::from(TILE_WIDTH);
-                let h = f64::from(TILE_HEIGHT);
-                let x = 1.0 + w * f64::from(u32::try_from(sq.c).unwrap());
-                let y = 1.0 + h * f64::from(u32::try_from(sq.r).unwrap());
-                ctx.draw_image_with_image_bitmap_and_dw_and_dh(img, x, y, w - 2.0, h - 2.0)
-                    .unwrap();
+                let rect = tile_size.tile_rect(sq);
+                draw_tile(&ctx, img, rect, self.settings.rounded_tiles);
+
+                if self.show_kinds {
+                    draw_tile_kind(&ctx, tile, rect);
+                }
             }
         }
 
         // 全体を暗くする。
         ctx.set_fill_style(&JsValue::from("rgba(0, 0, 0, 0.3)"));
-        ctx.fill_rect(
-            0.0,
-            0.0,
-            f64::from(canvas.width()),
-            f64::from(canvas.height()),
-        );
+        ctx.fill_rect(0.0, 0.0, f64::from(width), f64::from(height));
+
+        // 経路がなく取れない組の牌に、種類ごとの色で薄く印を付ける。
+        ctx.set_global_alpha(0.6);
+        ctx.set_line_width(3.0);
+        for &(sq1, sq2) in &self.blocked_pairs {
+            let BoardCell::Tile(tile) = self.board[sq1] else {
+                continue;
+            };
+            ctx.set_stroke_style(&JsValue::from(path_color(tile)));
+            for sq in [sq1, sq2] {
+                let (x, y, w, h) = tile_size.tile_rect(sq);
+                ctx.stroke_rect(x + 1.5, y + 1.5, w - 3.0, h - 3.0);
+            }
+        }
+        ctx.set_global_alpha(1.0);
     }
 
     fn view(&self) -> Node<Msg> {
@@ -495,22 +3452,155 @@ impl ModelStuck {
     }
 
     fn view_canvas(&self) -> Node<Msg> {
+        let (width, height) = self.settings.tile_size().canvas_size(&self.board);
+        let (backing_width, backing_height) = canvas_backing_size((width, height));
+
         div![canvas![
             el_ref(&self.el_canvas),
             el_key(&"stuck_canvas"),
             attrs! {
-                At::Width => px(CANVAS_WIDTH),
-                At::Height => px(CANVAS_HEIGHT),
+                At::Width => backing_width,
+                At::Height => backing_height,
+            },
+            style! {
+                St::Width => px(width),
+                St::Height => px(height),
             },
         ]]
     }
 
     fn view_ui(&self) -> Node<Msg> {
+        let lang = self.settings.lang();
+
         div![
             C!["ui"],
-            div![util::format_duration(self.elapsed)],
-            div!["STUCK..."],
-            div![button!["Restart", ev(Ev::Click, |_| Msg::Restart)]],
+            div![util::format_duration(self.elapsed())],
+            div![lang.tr(if self.time_up {
+                Text::TimeUp
+            } else {
+                Text::Stuck
+            })],
+            div![
+                button![lang.tr(Text::Restart), ev(Ev::Click, |_| Msg::Restart)],
+                " ",
+                button![
+                    lang.tr(Text::ReplayThisBoard),
+                    ev(Ev::Click, |_| Msg::ReplayBoard)
+                ],
+            ],
         ]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn click_action_on_empty_square() {
+        let board = Board::from_string_repr("4x1:0.0.", Rule::default()).unwrap();
+        let (tile, empty) = (Square::new(1, 1), Square::new(2, 1));
+
+        // 牌のないマスや盤面外のクリックは、選択中なら選択を解除し、そうでなければ無視する。
+        assert_eq!(click_action(&board, None, Some(empty)), ClickAction::Ignore);
+        assert_eq!(click_action(&board, None, None), ClickAction::Ignore);
+        assert_eq!(
+            click_action(&board, Some(tile), Some(empty)),
+            ClickAction::Deselect
+        );
+        assert_eq!(
+            click_action(&board, Some(tile), None),
+            ClickAction::Deselect
+        );
+    }
+
+    #[test]
+    fn click_action_on_tile() {
+        let board = Board::from_string_repr("2x2:0110", Rule::default()).unwrap();
+        let sq = |c, r| Square::new(c, r);
+
+        assert_eq!(
+            click_action(&board, None, Some(sq(1, 1))),
+            ClickAction::Select(sq(1, 1))
+        );
+        // 同じマス、取れない牌同士。
+        assert_eq!(
+            click_action(&board, Some(sq(1, 1)), Some(sq(1, 1))),
+            ClickAction::Deselect
+        );
+        assert_eq!(
+            click_action(&board, Some(sq(1, 1)), Some(sq(2, 1))),
+            ClickAction::Deselect
+        );
+        // 取れる牌同士だが経路がない。
+        assert_eq!(
+            click_action(&board, Some(sq(1, 1)), Some(sq(2, 2))),
+            ClickAction::Blocked(sq(1, 1), sq(2, 2))
+        );
+
+        let board = Board::from_string_repr("2x1:00", Rule::default()).unwrap();
+        let mv = board.shortest_move_between(sq(1, 1), sq(2, 1)).unwrap();
+        assert_eq!(
+            click_action(&board, Some(sq(1, 1)), Some(sq(2, 1))),
+            ClickAction::Play(mv)
+        );
+    }
+
+    #[test]
+    fn square_at_edges_and_margins() {
+        for tile_size in [TileAspect::Square.tile_size(), TileAspect::Tall.tile_size()] {
+            let (w, h) = (tile_size.width as i32, tile_size.height as i32);
+            let at = |x, y| tile_size.square_at(x, y, 4, 3);
+
+            // マスの境界。左上の端はそのマス、右下の端の 1px 外は隣のマス。
+            assert_eq!(at(0, 0), Some(Square::new(0, 0)));
+            assert_eq!(at(w - 1, h - 1), Some(Square::new(0, 0)));
+            assert_eq!(at(w, h - 1), Some(Square::new(1, 0)));
+            assert_eq!(at(w - 1, h), Some(Square::new(0, 1)));
+
+            // 牌の周囲の余白は、その牌のマスに含まれる。
+            let sq = Square::new(2, 1);
+            let (x, y, tw, th) = tile_size.tile_rect(sq);
+            let (x, y, tw, th) = (x as i32, y as i32, tw as i32, th as i32);
+            let margin = TILE_MARGIN as i32;
+            assert_eq!(at(x - margin, y - margin), Some(sq));
+            assert_eq!(at(x + tw + margin - 1, y + th + margin - 1), Some(sq));
+            assert_eq!(at(x + tw / 2, y + th / 2), Some(sq));
+
+            // 盤面外。
+            assert_eq!(at(-1, 0), None);
+            assert_eq!(at(0, -1), None);
+            assert_eq!(at(4 * w, 0), None);
+            assert_eq!(at(0, 3 * h), None);
+            assert_eq!(at(4 * w - 1, 3 * h - 1), Some(Square::new(3, 2)));
+        }
+    }
+
+    #[test]
+    fn play_clock_starts_on_first_move() {
+        let mut clock = PlayClock::waiting();
+        assert!(clock.is_waiting());
+        assert!(!clock.is_running());
+        // 最初の操作を待っている間は一時停止中とみなさない。
+        assert!(!clock.is_paused());
+
+        // 一時停止の解除やカウントダウンの終了では計測を始めない。
+        clock.resume();
+        assert!(clock.is_waiting());
+        assert!(!clock.is_running());
+        assert_eq!(clock.elapsed(), Duration::ZERO);
+
+        // 最初の着手で計測を始める。
+        clock.start_if_waiting();
+        assert!(!clock.is_waiting());
+        assert!(clock.is_running());
+
+        // 以降は通常どおり一時停止/再開できる。
+        clock.pause();
+        assert!(clock.is_paused());
+        clock.start_if_waiting();
+        assert!(clock.is_paused());
+        clock.resume();
+        assert!(clock.is_running());
+    }
+}